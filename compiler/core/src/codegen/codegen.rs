@@ -8,7 +8,7 @@ use crate::{
     parser::{
         parse_location::Parsed,
         ruulang_ast::{Attribute, Entrypoint, Fragment, RuuLangFile},
-        schema_ast::{Entity, Relationship},
+        schema_ast::{ContextVariable, Entity, Relationship},
     },
     utils::with_origin::WithOrigin,
 };
@@ -165,6 +165,13 @@ where
         None
     }
 
+    fn serialize_context(
+        &self,
+        _context: &Vec<Parsed<ContextVariable>>,
+    ) -> Option<CodegenState<Import>> {
+        None
+    }
+
     fn serialize_footer(&self) -> Option<CodegenState<Import>> {
         None
     }
@@ -216,6 +223,12 @@ where
             }
         }
 
+        if !file.context.is_empty() {
+            if let Some(new_state) = self.serialize_context(&file.context) {
+                state = state.concat(new_state);
+            }
+        }
+
         for entrypoint in &file.entrypoints {
             if let Some(new_state) = self.serialize_entrypoint(&entrypoint.data) {
                 state = state.concat(new_state);
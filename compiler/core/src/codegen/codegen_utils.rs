@@ -1,3 +1,5 @@
+use crate::typechecker::modules::split_reference;
+
 pub fn decompose(name: &str) -> Vec<String> {
     let mut acc = Vec::new();
     let mut local_acc = String::new();
@@ -50,3 +52,9 @@ pub fn _snake_case(name: &str) -> String {
 
     acc
 }
+
+/// Drops the module qualifier from a reference, so `auth::User` becomes
+/// `User`.
+pub fn unqualified(name: &str) -> &str {
+    split_reference(name).1
+}
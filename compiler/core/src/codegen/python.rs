@@ -4,9 +4,12 @@ use crate::{
     config::config::RuuLangConfig,
     parser::{
         parse_location::Parsed,
-        ruulang_ast::{Attribute, Entrypoint, Fragment, RuuLangFile},
-        schema_ast::{Entity, Relationship},
+        ruulang_ast::{
+            Attribute, AttributeParameter, ContextValue, Entrypoint, Fragment, RuuLangFile,
+        },
+        schema_ast::{ContextType, ContextVariable, Entity, Relationship},
     },
+    typechecker::tc_ast::TcEntity,
     utils::with_origin::WithOrigin,
 };
 
@@ -59,12 +62,48 @@ pub struct PythonCodegen<'a> {
     entities: &'a Vec<WithOrigin<Parsed<Entity>>>,
     config: &'a RuuLangConfig,
     file: &'a RuuLangFile,
+
+    /// The grants rules negate on each relationship, by entity and
+    /// relationship name
+    denied_grants: HashMap<(String, String), Vec<Vec<String>>>,
 }
 
 impl<'a> PythonCodegen<'a> {
+    pub fn with_denied_grants(
+        mut self,
+        denied_grants: HashMap<(String, String), Vec<Vec<String>>>,
+    ) -> Self {
+        self.denied_grants = denied_grants;
+        self
+    }
+
     fn new_codegen_helper(&self) -> CodegenHelper<'a> {
         CodegenHelper::new("    ", "\n")
     }
+
+    fn write_grant_path(s: &mut CodegenHelper<'_>, grant: &Vec<String>) {
+        s.write_token("tuple");
+        s.write_symbol("[");
+        s.iter_and_join(grant, ", ", |s, g| {
+            s.write("Literal[");
+            s.with_duouble_quote(|s| {
+                s.write(g);
+            });
+            s.write("]");
+        });
+        s.write_symbol("]");
+    }
+
+    fn write_permission(s: &mut CodegenHelper<'_>, grant: &Vec<String>) {
+        s.write("(");
+        s.iter_and_join(grant, ", ", |s, g| {
+            s.with_duouble_quote(|s| s.write(g));
+        });
+        if grant.len() == 1 {
+            s.write(",");
+        }
+        s.write(")");
+    }
 }
 
 impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
@@ -81,6 +120,7 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
             config,
             entities,
             file,
+            denied_grants: HashMap::new(),
         }
     }
 
@@ -239,6 +279,29 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
             s.with_duouble_quote(|s| s.write(&attribute.name));
             s.write("]");
             s.write_line(None);
+
+            // Rules may omit the arguments to match on the attribute alone
+            if let Some(parameters) = &attribute.parameters {
+                s.write("arguments: tuple[()]");
+
+                if !parameters.is_empty() {
+                    s.write(" | tuple[");
+                    s.iter_and_join(parameters, ", ", |s, parameter| match parameter {
+                        AttributeParameter::OneOf(values) => {
+                            s.write("Literal[");
+                            s.iter_and_join(values, ", ", |s, value| {
+                                s.with_duouble_quote(|s| s.write(value));
+                            });
+                            s.write("]");
+                        }
+                        AttributeParameter::Int => s.write("int"),
+                        AttributeParameter::String => s.write("str"),
+                    });
+                    s.write("]");
+                }
+
+                s.write_line(None);
+            }
         });
 
         let mut state = CodegenState::new();
@@ -265,20 +328,30 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
         s.write_line(Some(
             format!(
                 "@registry.register_relationship(\"{}\", \"{}\", \"{}\")",
-                &entity.name, &rule.relationship_name, &rule.entity_name,
+                &entity.name,
+                &rule.relationship_name,
+                codegen_utils::unqualified(&rule.entity_name),
             )
             .as_str(),
         ));
+        let key = (
+            entity.name.value.clone(),
+            rule.relationship_name.value.clone(),
+        );
+        let denied = self
+            .denied_grants
+            .get(&key)
+            .map_or(&[][..], |x| x.as_slice());
+
         PythonImport::with_class(&mut s, &rel_name, vec!["Rule"], |s| {
             s.write("relationship: Literal[");
             s.with_duouble_quote(|s| s.write(&rule.relationship_name));
             s.write("]");
             s.write_line(None);
 
-            let src_entity = self
-                .entities
-                .iter()
-                .find(|e| e.data.data.name.data == rule.entity_name.data);
+            let src_entity = self.entities.iter().find(|e| {
+                e.data.data.name.data.value == codegen_utils::unqualified(&rule.entity_name)
+            });
 
             let dst_entity = src_entity;
 
@@ -290,18 +363,18 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
             } else {
                 s.write("grants: tuple[");
                 s.iter_and_join(grants, " | ", |s, grant| {
-                    s.write_token("tuple");
-                    s.write_symbol("[");
-                    s.iter_and_join(&grant.data.grant, ", ", |s, g| {
-                        s.write("Literal[");
-                        s.with_duouble_quote(|s| {
-                            s.write(&g);
-                        });
-                        s.write("]");
-                    });
-                    s.write_symbol("]");
+                    PythonCodegen::write_grant_path(s, &grant.data.grant);
                 });
 
+                // Only the grants some rule negates can be denied
+                if !denied.is_empty() {
+                    s.write(" | Deny[");
+                    s.iter_and_join(denied, " | ", |s, grant| {
+                        PythonCodegen::write_grant_path(s, grant);
+                    });
+                    s.write("]");
+                }
+
                 s.write(", ...]");
             }
             s.write_line(None);
@@ -348,6 +421,9 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
             s.write_line(None);
         });
 
+        if !denied.is_empty() {
+            state.add_import(PythonImport::new_global("ruu_runtime", "Deny"));
+        }
         state.add_import(PythonImport::new_global("ruu_runtime", "Rule"));
         state.add_import(PythonImport::new_global("ruu_runtime", "Universal"));
         state.add_import(PythonImport::new_global("ruu_runtime", "registry"));
@@ -356,20 +432,121 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
         Some(state)
     }
 
+    fn serialize_entity(&self, entity: &Entity) -> Option<CodegenState<PythonImport>> {
+        // Implied grants may be declared in another part of the entity
+        let mut lattice = TcEntity::new(entity.name.data.value.clone());
+        for decl in self.entities {
+            if decl.data.data.name.data.value == entity.name.data.value {
+                for grant in &decl.data.data.grants {
+                    lattice.add_grant(grant.clone());
+                }
+            }
+        }
+
+        // Without a declared lattice the runtime covers grants by prefix
+        let register_grants = !entity.grants.is_empty() && lattice.declares_lattice();
+        if !register_grants && entity.universal.is_none() {
+            return None;
+        }
+
+        let mut s = self.new_codegen_helper();
+
+        if register_grants {
+            s.write(format!("registry.register_grants(\"{}\", {{", &entity.name).as_str());
+            s.write_line(None);
+            s.with_indent(|s| {
+                for grant in &entity.grants {
+                    PythonCodegen::write_permission(s, &grant.data.grant);
+                    let implied = lattice.implied_grants(&grant.data.grant);
+
+                    s.write(": (");
+                    s.iter_and_join(&implied, ", ", |s, implied| {
+                        PythonCodegen::write_permission(s, implied)
+                    });
+                    if implied.len() == 1 {
+                        s.write(",");
+                    }
+                    s.write("),");
+                    s.write_line(None);
+                }
+            });
+            s.write_line(Some("})"));
+        }
+
+        // Entities that don't declare their universal grants use the
+        // runtime's defaults
+        if let Some(universal) = &entity.universal {
+            s.write(format!("registry.register_universal(\"{}\", (", &entity.name).as_str());
+            s.iter_and_join(universal, ", ", |s, grant| {
+                PythonCodegen::write_permission(s, &grant.data.grant)
+            });
+            if universal.len() == 1 {
+                s.write(",");
+            }
+            s.write_line(Some("))"));
+        }
+
+        let mut state = CodegenState::new();
+        state.add_import(PythonImport::new_global("ruu_runtime", "registry"));
+        state.write_code(s.serialize());
+        Some(state)
+    }
+
+    fn serialize_context(
+        &self,
+        context: &Vec<Parsed<ContextVariable>>,
+    ) -> Option<CodegenState<PythonImport>> {
+        let mut s = self.new_codegen_helper();
+
+        // Variables missing from the context fail the conditions that use them
+        PythonImport::with_class(&mut s, "Context", vec!["TypedDict", "total=False"], |s| {
+            for variable in context {
+                s.write(format!("{}: ", &variable.data.name).as_str());
+                match &variable.data.kind {
+                    ContextType::Bool => s.write("bool"),
+                    ContextType::Int => s.write("int"),
+                    ContextType::String => s.write("str"),
+                    ContextType::OneOf(values) => {
+                        s.write("Literal[");
+                        s.iter_and_join(values, ", ", |s, value| match ContextValue::from_literal(
+                            value.to_string(),
+                        ) {
+                            ContextValue::Bool(true) => s.write("True"),
+                            ContextValue::Bool(false) => s.write("False"),
+                            ContextValue::Int(value) => s.write(&value.to_string()),
+                            ContextValue::String(value) => {
+                                s.with_duouble_quote(|s| s.write(&value))
+                            }
+                        });
+                        s.write("]");
+                    }
+                }
+                s.write_line(None);
+            }
+        });
+
+        let mut state = CodegenState::new();
+        state.add_import(PythonImport::new_global("typing", "Literal"));
+        state.add_import(PythonImport::new_global("typing", "TypedDict"));
+        state.write_code(s.serialize());
+        Some(state)
+    }
+
     fn serialize_fragment(&self, fragment: &Fragment) -> Option<CodegenState<PythonImport>> {
         let mut s = CodegenHelper::new("    ", "\n");
         let mut state = CodegenState::new();
 
         let cls_name = format!(
             "{}{}Fragment",
-            &codegen_utils::camel_case(&fragment.for_entity),
+            &codegen_utils::camel_case(codegen_utils::unqualified(&fragment.for_entity)),
             &codegen_utils::camel_case(&fragment.name)
         );
 
         s.write_line(Some(
             format!(
                 "@registry.register_fragment(\"{}\", \"{}\")",
-                &fragment.for_entity, &fragment.name
+                codegen_utils::unqualified(&fragment.for_entity),
+                &fragment.name
             )
             .as_str(),
         ));
@@ -384,16 +561,13 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
             s.write_symbol("tuple[");
 
             s.iter_and_join(&fragment.grants, " | ", |s, grant| {
-                s.write_token("tuple");
-                s.write_symbol("[");
-                s.iter_and_join(&grant.data.grant, ", ", |s, g| {
-                    s.write("Literal[");
-                    s.with_duouble_quote(|s| {
-                        s.write(&g);
-                    });
+                if grant.data.negated {
+                    s.write("Deny[");
+                    PythonCodegen::write_grant_path(s, &grant.data.grant);
                     s.write("]");
-                });
-                s.write_symbol("]");
+                } else {
+                    PythonCodegen::write_grant_path(s, &grant.data.grant);
+                }
             });
 
             s.write_symbol(", ...]");
@@ -405,12 +579,15 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
             s.iter_and_join(&fragment.rules, " | ", |s, rule| {
                 let rel_name = format!(
                     "{}{}Rule",
-                    &codegen_utils::camel_case(&fragment.for_entity),
+                    &codegen_utils::camel_case(codegen_utils::unqualified(&fragment.for_entity)),
                     &codegen_utils::camel_case(&rule.data.relationship),
                 );
 
                 s.write(&rel_name);
-                state.add_import(PythonImport::new_local(&fragment.for_entity, &rel_name))
+                state.add_import(PythonImport::new_local(
+                    codegen_utils::unqualified(&fragment.for_entity),
+                    &rel_name,
+                ))
             });
             s.write_symbol(", ...]\"");
             s.write_line(None);
@@ -418,6 +595,9 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
 
         state.write_code(s.serialize());
         state.add_import(PythonImport::new_global("ruu_runtime", "Fragment"));
+        if fragment.grants.iter().any(|grant| grant.data.negated) {
+            state.add_import(PythonImport::new_global("ruu_runtime", "Deny"));
+        }
         state.add_import(PythonImport::new_global("typing", "Literal"));
 
         Some(state)
@@ -429,7 +609,7 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
 
         let name = format!(
             "{}Entrypoint",
-            &codegen_utils::camel_case(&entrypoint.entrypoint)
+            &codegen_utils::camel_case(codegen_utils::unqualified(&entrypoint.entrypoint))
         );
 
         s.write_line(Some("@registry.bind"));
@@ -438,7 +618,7 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
             s.write_symbol(": ");
             s.write_symbol("Literal[");
             s.with_duouble_quote(|s| {
-                s.write(&entrypoint.entrypoint);
+                s.write(codegen_utils::unqualified(&entrypoint.entrypoint));
             });
             s.write_symbol("]");
             s.write_line(None);
@@ -449,12 +629,15 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
             s.iter_and_join(&entrypoint.rules, " | ", |s, rule| {
                 let rel_name = format!(
                     "{}{}Rule",
-                    &codegen_utils::camel_case(&entrypoint.entrypoint),
+                    &codegen_utils::camel_case(codegen_utils::unqualified(&entrypoint.entrypoint)),
                     &codegen_utils::camel_case(&rule.data.relationship),
                 );
 
                 s.write(&rel_name);
-                state.add_import(PythonImport::new_local(&entrypoint.entrypoint, &rel_name))
+                state.add_import(PythonImport::new_local(
+                    codegen_utils::unqualified(&entrypoint.entrypoint),
+                    &rel_name,
+                ))
             });
             s.write_symbol(", ...]\"");
             s.write_line(None);
@@ -492,7 +675,9 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
                 s.iter_and_join(entrypoints, " | ", |s, entrypoint| {
                     let entrypoint_name = format!(
                         "{}Entrypoint",
-                        &codegen_utils::camel_case(&entrypoint.data.entrypoint)
+                        &codegen_utils::camel_case(codegen_utils::unqualified(
+                            &entrypoint.data.entrypoint
+                        ))
                     );
 
                     s.write(&entrypoint_name);
@@ -508,7 +693,9 @@ impl<'a> Codegen<'a, PythonImport> for PythonCodegen<'a> {
                 s.iter_and_join(fragments, " | ", |s, fragment| {
                     let fragment_name = format!(
                         "{}{}Fragment",
-                        &codegen_utils::camel_case(&fragment.data.for_entity),
+                        &codegen_utils::camel_case(codegen_utils::unqualified(
+                            &fragment.data.for_entity
+                        )),
                         &codegen_utils::camel_case(&fragment.data.name)
                     );
 
@@ -1,4 +1,5 @@
 use crate::utils::error::Result;
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::str;
@@ -10,6 +11,10 @@ use serde::Deserialize;
 pub struct RuuLangConfig {
     pub workspace: ConfigWorkspace,
 
+    /// The level of each lint, by name. Lints not listed are warnings.
+    #[serde(default)]
+    pub lints: HashMap<String, LintLevel>,
+
     pub json: Option<JsonCodegen>,
     pub python: Option<PythonCodegen>,
 }
@@ -19,6 +24,17 @@ pub struct ConfigWorkspace {
     pub root: Option<PathBuf>,
 }
 
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// Not reported
+    Allow,
+    /// Reported as a warning
+    Warn,
+    /// Reported as an error
+    Deny,
+}
+
 #[derive(Deserialize, Debug, Default)]
 pub struct JsonCodegen {
     #[serde(default)]
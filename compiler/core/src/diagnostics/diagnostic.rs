@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use crate::utils::error::RuuLangError;
+
+pub use crate::utils::error::{Edit, Fix, Related, Severity};
+
+/// A problem in a file, along with any notes pointing at related parts of
+/// the workspace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub loc: Option<(usize, usize)>,
+    pub severity: Severity,
+    pub code: Option<String>,
+    pub message: String,
+    pub notes: Vec<Related>,
+    pub fix: Option<Fix>,
+}
+
+impl Diagnostic {
+    pub fn new(file: PathBuf, loc: Option<(usize, usize)>, message: String) -> Self {
+        Self {
+            file,
+            loc,
+            severity: Severity::Error,
+            code: None,
+            message,
+            notes: vec![],
+            fix: None,
+        }
+    }
+
+    pub fn from_error(file: PathBuf, error: &RuuLangError) -> Self {
+        Self {
+            file,
+            loc: error.loc(),
+            severity: error.severity(),
+            code: Some(error.code().to_string()),
+            message: error.to_string(),
+            notes: error.related().to_vec(),
+            fix: error.fix().cloned(),
+        }
+    }
+}
+
+/// The zero-based line and column of `offset` in `source`. Columns are
+/// counted in characters.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let offset = floor_char_boundary(source, offset);
+    let line_start = source[..offset].rfind('\n').map_or(0, |x| x + 1);
+
+    (
+        source[..offset].matches('\n').count(),
+        source[line_start..offset].chars().count(),
+    )
+}
+
+/// Widens `loc` to its whole line, including the line break, when nothing
+/// else is written on that line.
+pub fn whole_line(source: &str, (start, end): (usize, usize)) -> (usize, usize) {
+    let start = floor_char_boundary(source, start);
+    let end = floor_char_boundary(source, end.max(start));
+
+    let line_start = source[..start].rfind('\n').map_or(0, |x| x + 1);
+    let line_end = source[end..]
+        .find('\n')
+        .map_or(source.len(), |x| end + x + 1);
+
+    match source[line_start..start].trim().is_empty() && source[end..line_end].trim().is_empty() {
+        true => (line_start, line_end),
+        false => (start, end),
+    }
+}
+
+pub(crate) fn floor_char_boundary(source: &str, index: usize) -> usize {
+    let mut index = index.min(source.len());
+    while !source.is_char_boundary(index) {
+        index -= 1;
+    }
+
+    index
+}
@@ -0,0 +1,248 @@
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use super::{
+    diagnostic::{line_column, Diagnostic, Severity},
+    renderer::DiagnosticRenderer,
+};
+
+/// How diagnostics are written out by `ruu`.
+#[derive(Debug, Clone)]
+pub enum MessageFormat {
+    /// Rendered for people to read
+    Human(DiagnosticRenderer),
+
+    /// One JSON object per diagnostic, each on its own line
+    Json,
+
+    /// A single SARIF 2.1.0 log, as consumed by code scanning tools
+    Sarif,
+}
+
+impl MessageFormat {
+    pub fn is_human(&self) -> bool {
+        matches!(self, MessageFormat::Human(_))
+    }
+
+    /// Writes out every diagnostic. `source` looks up the contents of a
+    /// diagnostic's file, which are needed to turn offsets into lines and
+    /// columns.
+    pub fn emit<'s>(
+        &self,
+        diagnostics: &[Diagnostic],
+        source: impl Fn(&Path) -> Option<&'s str>,
+    ) -> String {
+        match self {
+            MessageFormat::Human(renderer) => diagnostics
+                .iter()
+                .map(|diagnostic| renderer.render(diagnostic, &source))
+                .collect(),
+            MessageFormat::Json => diagnostics
+                .iter()
+                .map(|diagnostic| {
+                    let record = json_record(diagnostic, &source);
+                    format!("{}\n", record)
+                })
+                .collect(),
+            MessageFormat::Sarif => {
+                let log = sarif_log(diagnostics, source);
+                format!("{}\n", serde_json::to_string_pretty(&log).unwrap())
+            }
+        }
+    }
+}
+
+/// A one-based line and column range, with an exclusive end column.
+fn range(
+    source: Option<&str>,
+    loc: Option<(usize, usize)>,
+) -> Option<((usize, usize), (usize, usize))> {
+    let (source, (start, end)) = source.zip(loc)?;
+    let (start_line, start_column) = line_column(source, start);
+    let (end_line, end_column) = line_column(source, end.max(start));
+
+    Some((
+        (start_line + 1, start_column + 1),
+        (end_line + 1, end_column + 1),
+    ))
+}
+
+fn json_range(source: Option<&str>, loc: Option<(usize, usize)>) -> Value {
+    match range(source, loc) {
+        Some(((start_line, start_column), (end_line, end_column))) => json!({
+            "start": { "line": start_line, "column": start_column },
+            "end": { "line": end_line, "column": end_column },
+        }),
+        None => Value::Null,
+    }
+}
+
+fn json_record<'s>(diagnostic: &Diagnostic, sources: impl Fn(&Path) -> Option<&'s str>) -> Value {
+    let source = sources(&diagnostic.file);
+
+    json!({
+        "file": file_uri(&diagnostic.file),
+        "range": json_range(source, diagnostic.loc),
+        "severity": diagnostic.severity,
+        "code": diagnostic.code,
+        "message": diagnostic.message,
+        "notes": diagnostic
+            .notes
+            .iter()
+            .map(|note| {
+                let mut record = json!({
+                    "range": json_range(source, note.loc),
+                    "message": note.message,
+                });
+
+                if let Some(file) = &note.file {
+                    record["file"] = json!(file_uri(file));
+                    record["range"] = json_range(sources(file), note.loc);
+                }
+
+                record
+            })
+            .collect::<Vec<_>>(),
+        "fix": diagnostic.fix.as_ref().map(|fix| json!({
+            "message": fix.message,
+            "edits": fix
+                .edits
+                .iter()
+                .map(|edit| json!({
+                    "range": json_range(source, Some(edit.loc)),
+                    "replacement": edit.replacement,
+                }))
+                .collect::<Vec<_>>(),
+        })),
+    })
+}
+
+fn sarif_location(file: &Path, source: Option<&str>, loc: Option<(usize, usize)>) -> Value {
+    let mut location = json!({
+        "artifactLocation": { "uri": file_uri(file) },
+    });
+
+    if let Some(region) = sarif_region(source, loc) {
+        location["region"] = region;
+    }
+
+    json!({ "physicalLocation": location })
+}
+
+fn sarif_region(source: Option<&str>, loc: Option<(usize, usize)>) -> Option<Value> {
+    let (start, end) = range(source, loc)?;
+    let source = source?;
+
+    Some(json!({
+        "startLine": start.0,
+        "startColumn": utf16_column(source, start),
+        "endLine": end.0,
+        "endColumn": utf16_column(source, end),
+    }))
+}
+
+/// Recounts the one-based character column of a one-based line in UTF-16
+/// code units, which is how SARIF counts columns by default.
+fn utf16_column(source: &str, (line, column): (usize, usize)) -> usize {
+    let text = source.split('\n').nth(line - 1).unwrap_or_default();
+
+    text.chars()
+        .take(column - 1)
+        .map(char::len_utf16)
+        .sum::<usize>()
+        + 1
+}
+
+fn sarif_log<'s>(diagnostics: &[Diagnostic], sources: impl Fn(&Path) -> Option<&'s str>) -> Value {
+    let mut rules = diagnostics
+        .iter()
+        .filter_map(|x| x.code.as_deref())
+        .collect::<Vec<_>>();
+    rules.sort();
+    rules.dedup();
+
+    let results = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let source = sources(&diagnostic.file);
+            let level = match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Info => "note",
+            };
+
+            let mut result = json!({
+                "level": level,
+                "message": { "text": diagnostic.message },
+                "locations": [sarif_location(&diagnostic.file, source, diagnostic.loc)],
+            });
+
+            if let Some(code) = &diagnostic.code {
+                result["ruleId"] = json!(code);
+            }
+
+            if !diagnostic.notes.is_empty() {
+                result["relatedLocations"] = diagnostic
+                    .notes
+                    .iter()
+                    .enumerate()
+                    .map(|(id, note)| {
+                        let file = note.file.as_ref().unwrap_or(&diagnostic.file);
+                        let mut location = sarif_location(file, sources(file), note.loc);
+                        location["id"] = json!(id);
+                        location["message"] = json!({ "text": note.message });
+                        location
+                    })
+                    .collect();
+            }
+
+            if let Some(fix) = &diagnostic.fix {
+                let replacements = fix
+                    .edits
+                    .iter()
+                    .map(|edit| {
+                        let mut replacement = json!({
+                            "insertedContent": { "text": edit.replacement },
+                        });
+                        if let Some(region) = sarif_region(source, Some(edit.loc)) {
+                            replacement["deletedRegion"] = region;
+                        }
+
+                        replacement
+                    })
+                    .collect::<Vec<_>>();
+
+                result["fixes"] = json!([{
+                    "description": { "text": fix.message },
+                    "artifactChanges": [{
+                        "artifactLocation": { "uri": file_uri(&diagnostic.file) },
+                        "replacements": replacements,
+                    }],
+                }]);
+            }
+
+            result
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "ruu",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.iter().map(|id| json!({ "id": id })).collect::<Vec<_>>(),
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// Paths are written with forward slashes, as SARIF expects URIs.
+fn file_uri(file: &Path) -> String {
+    file.to_string_lossy().replace('\\', "/")
+}
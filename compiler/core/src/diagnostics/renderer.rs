@@ -0,0 +1,264 @@
+use std::{env, io::IsTerminal, path::Path};
+
+use super::diagnostic::{floor_char_boundary, line_column, Diagnostic, Severity};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const ERROR: &str = "\x1b[1;31m";
+const WARNING: &str = "\x1b[1;33m";
+const INFO: &str = "\x1b[1;36m";
+const NOTE: &str = "\x1b[1;32m";
+const GUTTER: &str = "\x1b[1;34m";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    /// Color output when stdout is a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderStyle {
+    /// A source excerpt with each span underlined
+    Rich,
+
+    /// A single `file:line:column: message` line per error and note, for
+    /// logs and tools that match on lines
+    Plain,
+}
+
+/// Renders diagnostics for people to read.
+#[derive(Debug, Clone)]
+pub struct DiagnosticRenderer {
+    color: bool,
+    style: RenderStyle,
+}
+
+/// Where a span starts within its file.
+struct Position<'s> {
+    line: usize,
+    column: usize,
+    line_text: &'s str,
+    start: usize,
+    end: usize,
+}
+
+impl DiagnosticRenderer {
+    pub fn new(color: ColorChoice, style: RenderStyle) -> Self {
+        Self {
+            color: color.enabled(),
+            style,
+        }
+    }
+
+    /// Renders `diagnostic`, taking excerpts from the contents `source`
+    /// looks up for each file, when they are available.
+    pub fn render<'s>(
+        &self,
+        diagnostic: &Diagnostic,
+        source: impl Fn(&Path) -> Option<&'s str>,
+    ) -> String {
+        let mut result = String::new();
+
+        let style = match diagnostic.severity {
+            Severity::Error => ERROR,
+            Severity::Warning => WARNING,
+            Severity::Info => INFO,
+        };
+        let level = match &diagnostic.code {
+            Some(code) => format!("{}[{}]", diagnostic.severity, code),
+            None => diagnostic.severity.to_string(),
+        };
+
+        let labels = std::iter::once((
+            level,
+            style,
+            '^',
+            diagnostic.file.as_path(),
+            diagnostic.loc,
+            &diagnostic.message,
+        ))
+        .chain(diagnostic.notes.iter().map(|note| {
+            let file = note.file.as_deref().unwrap_or(&diagnostic.file);
+            ("note".to_string(), NOTE, '-', file, note.loc, &note.message)
+        }))
+        .collect::<Vec<_>>();
+
+        let positions = labels
+            .iter()
+            .map(|(_, _, _, file, loc, _)| {
+                source(file)
+                    .zip(*loc)
+                    .map(|(source, loc)| position(source, loc))
+            })
+            .collect::<Vec<_>>();
+
+        let gutter_width = positions
+            .iter()
+            .flatten()
+            .map(|x| (x.line + 1).to_string().len())
+            .max()
+            .unwrap_or(0);
+
+        for ((level, style, marker, file, _, message), position) in labels.iter().zip(&positions) {
+            let file = file.display();
+
+            match self.style {
+                RenderStyle::Plain => {
+                    let location = match position {
+                        Some(x) => format!("{}:{}:{}", file, x.line + 1, x.column + 1),
+                        None => file.to_string(),
+                    };
+
+                    result.push_str(
+                        format!("{}: {}: {}\n", location, level, message.replace('\n', " "))
+                            .as_str(),
+                    );
+                }
+                RenderStyle::Rich => {
+                    let indent = " ".repeat(gutter_width);
+
+                    result.push_str(
+                        format!(
+                            "{}: {}\n",
+                            self.paint(style, level),
+                            self.paint(BOLD, message)
+                        )
+                        .as_str(),
+                    );
+
+                    let Some(position) = position else {
+                        result.push_str(
+                            format!("{}{} {}\n", indent, self.paint(GUTTER, "-->"), file).as_str(),
+                        );
+                        continue;
+                    };
+
+                    let line_number = (position.line + 1).to_string();
+                    let bar = self.paint(GUTTER, "|");
+
+                    // Keep tabs in the padding so the underline lines up
+                    let padding = position.line_text[..position.start]
+                        .chars()
+                        .map(|c| if c == '\t' { '\t' } else { ' ' })
+                        .collect::<String>();
+                    let width = position.line_text[position.start..position.end]
+                        .chars()
+                        .count()
+                        .max(1);
+                    let underline = marker.to_string().repeat(width);
+
+                    result.push_str(
+                        format!(
+                            "{}{} {}:{}:{}\n",
+                            indent,
+                            self.paint(GUTTER, "-->"),
+                            file,
+                            line_number,
+                            position.column + 1
+                        )
+                        .as_str(),
+                    );
+                    result.push_str(format!("{} {}\n", indent, bar).as_str());
+                    result.push_str(
+                        format!(
+                            "{} {} {}\n",
+                            self.paint(GUTTER, &format!("{:>1$}", line_number, gutter_width)),
+                            bar,
+                            position.line_text
+                        )
+                        .as_str(),
+                    );
+                    result.push_str(
+                        format!(
+                            "{} {} {}{}\n",
+                            indent,
+                            bar,
+                            padding,
+                            self.paint(style, &underline)
+                        )
+                        .as_str(),
+                    );
+                }
+            }
+        }
+
+        if let Some(fix) = &diagnostic.fix {
+            match self.style {
+                RenderStyle::Plain => {
+                    let location = match positions.first().and_then(|x| x.as_ref()) {
+                        Some(x) => format!(
+                            "{}:{}:{}",
+                            diagnostic.file.display(),
+                            x.line + 1,
+                            x.column + 1
+                        ),
+                        None => diagnostic.file.display().to_string(),
+                    };
+
+                    result.push_str(format!("{}: help: {}\n", location, fix.message).as_str());
+                }
+                RenderStyle::Rich => result.push_str(
+                    format!(
+                        "{} {} {}: {}\n",
+                        " ".repeat(gutter_width),
+                        self.paint(GUTTER, "="),
+                        self.paint(BOLD, "help"),
+                        fix.message
+                    )
+                    .as_str(),
+                ),
+            }
+        }
+
+        // Separate excerpts from whatever follows them
+        if self.style == RenderStyle::Rich {
+            result.push('\n');
+        }
+
+        result
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        match self.color {
+            true => format!("{}{}{}", style, text, RESET),
+            false => text.to_string(),
+        }
+    }
+}
+
+/// Finds the line a span starts on. Spans that run across several lines are
+/// cut off at the end of their first line.
+fn position(source: &str, (start, end): (usize, usize)) -> Position<'_> {
+    let start = floor_char_boundary(source, start);
+    let end = floor_char_boundary(source, end.max(start));
+
+    let (line, column) = line_column(source, start);
+
+    let line_start = source[..start].rfind('\n').map_or(0, |x| x + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |x| start + x);
+    let line_text = source[line_start..line_end].trim_end_matches('\r');
+
+    Position {
+        line,
+        column,
+        line_text,
+        start: (start - line_start).min(line_text.len()),
+        end: (end.min(line_end) - line_start).min(line_text.len()),
+    }
+}
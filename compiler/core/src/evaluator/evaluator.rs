@@ -0,0 +1,781 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use serde::Serialize;
+
+use crate::{
+    parser::{
+        parse_location::Parsed,
+        ruulang_ast::{ContextValue, Entrypoint, Fragment, Grant, Rule, RuuLangFile},
+        schema_ast::Entity,
+    },
+    typechecker::{
+        tc_ast::TcEntity,
+        typechecker::{FragmentDecl, Typechecker},
+    },
+    utils::{
+        error::{EvaluationError, Result, RuuLangError},
+        with_origin::WithOrigin,
+    },
+};
+
+use super::{
+    explanation::{
+        AccessDecision, AttributeMatch, Explanation, ExplanationStep, GrantSource, SourceRef,
+    },
+    fact_store::{FactStore, Node},
+    grant_set::GrantSet,
+    search::{Parent, ReverseWalk, RuleGraph, Visit, Walk},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct EdgeAttribute {
+    pub name: String,
+    pub arguments: Vec<String>,
+}
+
+impl EdgeAttribute {
+    pub fn new(name: &str, arguments: Vec<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            arguments: arguments.into_iter().map(|x| x.to_string()).collect(),
+        }
+    }
+}
+
+/// A single traversed relationship, e.g. `personnel:authorization(admin)`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Edge {
+    pub relationship: String,
+    pub attributes: Vec<EdgeAttribute>,
+}
+
+impl Edge {
+    pub fn new(relationship: &str, attributes: Vec<EdgeAttribute>) -> Self {
+        Self {
+            relationship: relationship.to_string(),
+            attributes,
+        }
+    }
+}
+
+/// A rule along with the file, module and fragment it was declared in.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RuleRef<'a> {
+    pub rule: &'a Parsed<Rule>,
+    pub origin: &'a PathBuf,
+    pub module: &'a str,
+    pub fragment: Option<&'a Parsed<Fragment>>,
+}
+
+impl<'a> RuleRef<'a> {
+    pub fn key(&self) -> *const Rule {
+        &self.rule.data as *const Rule
+    }
+}
+
+/// A grant along with the file and fragment it was declared in.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct GrantRef<'a> {
+    pub grant: &'a Parsed<Grant>,
+    pub origin: &'a PathBuf,
+    pub fragment: Option<&'a Parsed<Fragment>>,
+}
+
+impl<'a> GrantRef<'a> {
+    pub fn source(&self) -> GrantSource {
+        GrantSource {
+            grant: self.grant.data.clone(),
+            source: SourceRef::new(self.origin, self.grant),
+            fragment: self
+                .fragment
+                .map(|fragment| SourceRef::new(self.origin, fragment)),
+        }
+    }
+}
+
+type EntrypointDecl<'a> = (&'a PathBuf, &'a str, &'a Parsed<Entrypoint>);
+
+#[derive(Debug, Clone)]
+pub struct Evaluator<'a> {
+    typechecker: Typechecker<'a>,
+
+    /// Keyed by the resolved name of the entrypoint's entity
+    entrypoints: HashMap<String, Vec<EntrypointDecl<'a>>>,
+
+    /// The runtime context that rule conditions are checked against
+    context: HashMap<String, ContextValue>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(
+        entities: &'a Vec<WithOrigin<Parsed<Entity>>>,
+        schemas: &'a Vec<WithOrigin<Result<RuuLangFile>>>,
+    ) -> Self {
+        let typechecker = Typechecker::new(entities, schemas);
+        let mut entrypoints = HashMap::<String, Vec<_>>::new();
+
+        for schema in schemas {
+            let Ok(file) = &schema.data else {
+                continue;
+            };
+
+            for entrypoint in &file.entrypoints {
+                let Ok(entity) = typechecker
+                    .modules()
+                    .resolve_entity(&file.module, &entrypoint.data.entrypoint.data.value)
+                else {
+                    continue;
+                };
+
+                entrypoints.entry(entity).or_default().push((
+                    &schema.origin,
+                    file.module.as_str(),
+                    entrypoint,
+                ));
+            }
+        }
+
+        Self {
+            typechecker,
+            entrypoints,
+            context: HashMap::new(),
+        }
+    }
+
+    /// Evaluates rules against `context`. A rule with conditions only applies
+    /// when each of them holds, so without a context it never does.
+    pub fn with_context(mut self, context: HashMap<String, ContextValue>) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Resolves the grants held on the entity reached by following `path`
+    /// from `entrypoint`.
+    pub fn evaluate(&self, entrypoint: &str, path: &[Edge]) -> Result<GrantSet> {
+        let mut current_entity = self.entity(entrypoint)?;
+        let mut active_rules = self.entrypoint_rules(entrypoint);
+        let mut matched_rules = vec![];
+
+        for edge in path {
+            let next_entity = self.traverse(current_entity, edge)?;
+
+            matched_rules = active_rules
+                .into_iter()
+                .filter(|rule| rule_matches(rule.rule, edge))
+                .collect::<Vec<_>>();
+
+            active_rules = dedup_rules(
+                matched_rules
+                    .iter()
+                    .flat_map(|rule| self.child_rules(rule, next_entity)),
+            );
+
+            current_entity = next_entity;
+        }
+
+        let mut grants = GrantSet::new();
+        for rule in matched_rules {
+            grants.extend(
+                current_entity,
+                self.rule_grants(&rule, current_entity)
+                    .into_iter()
+                    .map(|x| &x.grant.data),
+            );
+        }
+
+        Ok(grants)
+    }
+
+    /// Decides whether any path through `store` from `subject` yields
+    /// `grant` on `object`.
+    pub fn can<S: FactStore + ?Sized>(
+        &self,
+        store: &S,
+        subject: &Node,
+        grant: &[String],
+        object: &Node,
+    ) -> Result<bool> {
+        self.entity(&subject.entity)?;
+        self.entity(&object.entity)?;
+
+        let allowed = Walk::new(self, store, subject)
+            .filter(|visit| &visit.node == object)
+            .any(|visit| self.visit_grants(&visit).allows(grant));
+
+        Ok(allowed)
+    }
+
+    /// Like [`Evaluator::can`], but also explains the decision. A denied
+    /// check is explained by its closest near-miss: the shortest path that
+    /// reached the object without the grant, or failing that the longest path
+    /// that reached an entity of the same type. When a negated grant withheld
+    /// the grant on that path, it is reported as the denial.
+    pub fn check<S: FactStore + ?Sized>(
+        &self,
+        store: &S,
+        subject: &Node,
+        grant: &[String],
+        object: &Node,
+    ) -> Result<AccessDecision> {
+        self.entity(&subject.entity)?;
+        self.entity(&object.entity)?;
+
+        let mut walk = Walk::new(self, store, subject);
+        let mut near_miss: Option<(bool, usize, usize, Option<GrantSource>)> = None;
+
+        while let Some(visit) = walk.next() {
+            let reached_object = &visit.node == object;
+            let mut denial = None;
+
+            if reached_object {
+                let object_entity = self.entity(&object.entity)?;
+                let grant_refs = self.visit_grant_refs(&visit);
+                let covering = |negated: bool| {
+                    grant_refs.iter().find(|grant_ref| {
+                        grant_ref.grant.data.negated == negated
+                            && object_entity.implies(&grant_ref.grant.data.grant, grant)
+                    })
+                };
+
+                if self.visit_grants(&visit).allows(grant) {
+                    if let Some(grant_ref) = covering(false) {
+                        let mut explanation = self.explain(&walk, visit.index);
+                        explanation.grant = Some(grant_ref.source());
+
+                        return Ok(AccessDecision {
+                            allowed: true,
+                            explanation: Some(explanation),
+                        });
+                    }
+                }
+
+                denial = covering(true).map(|grant_ref| grant_ref.source());
+            }
+
+            if reached_object || visit.node.entity == object.entity {
+                let depth = walk.depth(visit.index);
+                let is_closer = match near_miss {
+                    None => true,
+                    Some((true, ..)) => false,
+                    Some((false, best_depth, ..)) => reached_object || depth > best_depth,
+                };
+
+                if is_closer {
+                    near_miss = Some((reached_object, depth, visit.index, denial));
+                }
+            }
+        }
+
+        Ok(AccessDecision {
+            allowed: false,
+            explanation: near_miss.map(|(_, _, index, denial)| Explanation {
+                denial,
+                ..self.explain(&walk, index)
+            }),
+        })
+    }
+
+    /// Lazily lists every object on which `subject` holds `grant`, walking
+    /// the entrypoint rules forward over `store`.
+    pub fn reachable_objects<'e, S: FactStore + ?Sized>(
+        &'e self,
+        store: &'e S,
+        subject: &Node,
+        grant: &[String],
+    ) -> Result<impl Iterator<Item = Node> + 'e> {
+        self.entity(&subject.entity)?;
+
+        let grant = grant.to_vec();
+        let mut seen = HashSet::new();
+
+        let objects = Walk::new(self, store, subject)
+            .filter(move |visit| self.visit_grants(visit).allows(&grant))
+            .map(|visit| visit.node)
+            .filter(move |node| seen.insert(node.clone()));
+
+        Ok(objects)
+    }
+
+    /// Lazily lists every subject holding `grant` on `object`, walking the
+    /// rules backward from `object` towards their entrypoints.
+    pub fn subjects_with<'e, S: FactStore + ?Sized>(
+        &'e self,
+        store: &'e S,
+        grant: &[String],
+        object: &Node,
+    ) -> Result<impl Iterator<Item = Node> + 'e> {
+        let object_entity = self.entity(&object.entity)?;
+
+        let walk = ReverseWalk::new(store, self.rule_graph(), object, |rule| {
+            let mut grants = GrantSet::new();
+            grants.extend(
+                object_entity,
+                self.rule_grants(rule, object_entity)
+                    .into_iter()
+                    .map(|x| &x.grant.data),
+            );
+
+            grants.allows(grant)
+        });
+
+        Ok(walk)
+    }
+
+    /// Indexes every entrypoint and fragment rule by the rules that can lead
+    /// to it.
+    pub(crate) fn rule_graph(&self) -> RuleGraph<'a> {
+        let mut graph = RuleGraph {
+            rules: vec![],
+            parents: HashMap::new(),
+        };
+
+        for (entity, entrypoints) in &self.entrypoints {
+            for (origin, module, entrypoint) in entrypoints {
+                for rule in &entrypoint.data.rules {
+                    let rule = RuleRef {
+                        rule,
+                        origin,
+                        module,
+                        fragment: None,
+                    };
+                    let parent = Parent::Entrypoint(entity.clone());
+                    self.index_rule(&mut graph, rule, Some(parent));
+                }
+            }
+        }
+
+        for (_, decl) in self.typechecker.fragments() {
+            for rule in &decl.fragment.data.rules {
+                let rule = RuleRef {
+                    rule,
+                    origin: decl.origin,
+                    module: decl.module,
+                    fragment: Some(decl.fragment),
+                };
+                self.index_rule(&mut graph, rule, None);
+            }
+        }
+
+        graph
+    }
+
+    fn index_rule(&self, graph: &mut RuleGraph<'a>, rule: RuleRef<'a>, parent: Option<Parent<'a>>) {
+        // A rule whose conditions fail can't lead anywhere
+        if !self.conditions_hold(&rule.rule.data) {
+            return;
+        }
+
+        graph.rules.push(rule);
+
+        let parents = graph.parents.entry(rule.key()).or_default();
+        parents.extend(parent);
+
+        if rule.rule.data.relationship.data.value == "*" {
+            parents.push(Parent::Rule { rule, target: None });
+        }
+
+        for included in &rule.rule.data.include_fragments {
+            // Only the fragments the include resolves to for their entity
+            let fragments = self.typechecker.fragments().filter(|(entity, decl)| {
+                self.typechecker
+                    .resolve_fragment(rule.module, &included.data.value, entity)
+                    .is_ok_and(|found| std::ptr::eq(found.fragment, decl.fragment))
+            });
+
+            for (entity, decl) in fragments {
+                for child in &decl.fragment.data.rules {
+                    let parent = Parent::Rule {
+                        rule,
+                        target: Some(entity.to_string()),
+                    };
+                    graph
+                        .parents
+                        .entry(&child.data as *const Rule)
+                        .or_default()
+                        .push(parent);
+                }
+            }
+        }
+
+        for child in &rule.rule.data.rules {
+            let child = RuleRef {
+                rule: child,
+                ..rule
+            };
+            let parent = Parent::Rule { rule, target: None };
+            self.index_rule(graph, child, Some(parent));
+        }
+    }
+
+    fn explain<S: FactStore + ?Sized>(&self, walk: &Walk<'_, 'a, S>, index: usize) -> Explanation {
+        let chain = walk.chain(index);
+        let root = chain[0];
+
+        let entrypoint = self
+            .entrypoints
+            .get(&root.fact.subject.entity)
+            .into_iter()
+            .flatten()
+            .find(|(_, _, entrypoint)| {
+                entrypoint
+                    .data
+                    .rules
+                    .iter()
+                    .any(|rule| std::ptr::eq(rule, root.rule.rule))
+            })
+            .map(|(origin, _, entrypoint)| SourceRef::new(origin, entrypoint))
+            .unwrap_or_else(|| SourceRef {
+                origin: root.rule.origin.clone(),
+                loc: None,
+            });
+
+        let steps = chain
+            .into_iter()
+            .map(|step| {
+                let attributes = step
+                    .rule
+                    .rule
+                    .data
+                    .attributes
+                    .iter()
+                    .map(|attr| AttributeMatch {
+                        source: SourceRef::new(step.rule.origin, attr),
+                        name: attr.data.name.data.value.clone(),
+                        arguments: step
+                            .fact
+                            .edge
+                            .attributes
+                            .iter()
+                            .find(|edge_attr| edge_attr.name == attr.data.name.data.value)
+                            .map_or(vec![], |edge_attr| edge_attr.arguments.clone()),
+                    })
+                    .collect();
+
+                ExplanationStep {
+                    fact: step.fact.clone(),
+                    rule: SourceRef::new(step.rule.origin, step.rule.rule),
+                    fragment: step
+                        .rule
+                        .fragment
+                        .map(|fragment| SourceRef::new(step.rule.origin, fragment)),
+                    attributes,
+                }
+            })
+            .collect();
+
+        Explanation {
+            entrypoint,
+            steps,
+            grant: None,
+            denial: None,
+        }
+    }
+
+    pub(crate) fn visit_grants(&self, visit: &Visit<'a>) -> GrantSet {
+        let mut grants = GrantSet::new();
+        if let Ok(entity) = self.entity(&visit.node.entity) {
+            grants.extend(
+                entity,
+                self.visit_grant_refs(visit)
+                    .into_iter()
+                    .map(|x| &x.grant.data),
+            );
+        }
+
+        grants
+    }
+
+    fn visit_grant_refs(&self, visit: &Visit<'a>) -> Vec<GrantRef<'a>> {
+        match self.entity(&visit.node.entity) {
+            Ok(entity) => self.rule_grants(&visit.rule, entity),
+            Err(_) => vec![],
+        }
+    }
+
+    pub(crate) fn entity(&self, name: &str) -> Result<&TcEntity> {
+        self.typechecker.entity(name).ok_or_else(|| {
+            RuuLangError::EvaluationError(EvaluationError::UnknownEntity(name.to_string()))
+        })
+    }
+
+    pub(crate) fn entrypoint_rules(&self, entity: &str) -> Vec<RuleRef<'a>> {
+        self.entrypoints
+            .get(entity)
+            .into_iter()
+            .flatten()
+            .flat_map(|(origin, module, entrypoint)| {
+                entrypoint.data.rules.iter().map(|rule| RuleRef {
+                    rule,
+                    origin,
+                    module,
+                    fragment: None,
+                })
+            })
+            .filter(|rule| self.conditions_hold(&rule.rule.data))
+            .collect()
+    }
+
+    /// Finds the entity on the other side of `edge`.
+    pub(crate) fn traverse(&self, from: &TcEntity, edge: &Edge) -> Result<&TcEntity> {
+        let relationship = from.get_rule(&edge.relationship).ok_or_else(|| {
+            RuuLangError::EvaluationError(EvaluationError::UnknownRelationship(
+                from.name.clone(),
+                edge.relationship.clone(),
+            ))
+        })?;
+
+        self.entity(&relationship.data.entity_name.data.value)
+    }
+
+    /// The rules that may match the next edge once `rule` has matched.
+    /// Universal rules stay active for every descendant.
+    pub(crate) fn child_rules(&self, rule: &RuleRef<'a>, target: &TcEntity) -> Vec<RuleRef<'a>> {
+        let mut children = rule
+            .rule
+            .data
+            .rules
+            .iter()
+            .map(|child| RuleRef {
+                rule: child,
+                ..*rule
+            })
+            .collect::<Vec<_>>();
+
+        if rule.rule.data.relationship.data.value == "*" {
+            children.push(*rule);
+        }
+
+        for decl in self.included_fragments(rule, target) {
+            children.extend(decl.fragment.data.rules.iter().map(|child| RuleRef {
+                rule: child,
+                origin: decl.origin,
+                module: decl.module,
+                fragment: Some(decl.fragment),
+            }));
+        }
+
+        children.retain(|child| self.conditions_hold(&child.rule.data));
+        children
+    }
+
+    /// Whether every condition of `rule` holds in the context. A variable
+    /// missing from the context fails its condition.
+    pub(crate) fn conditions_hold(&self, rule: &Rule) -> bool {
+        rule.conditions.iter().all(|condition| {
+            self.context
+                .get(&condition.data.variable.data.value)
+                .is_some_and(|value| condition.data.holds(value))
+        })
+    }
+
+    /// The grants a matched rule confers on its target, including those of
+    /// any included fragments.
+    pub(crate) fn rule_grants(&self, rule: &RuleRef<'a>, target: &TcEntity) -> Vec<GrantRef<'a>> {
+        let mut grants = rule
+            .rule
+            .data
+            .grants
+            .iter()
+            .map(|grant| GrantRef {
+                grant,
+                origin: rule.origin,
+                fragment: rule.fragment,
+            })
+            .collect::<Vec<_>>();
+
+        if rule.rule.data.universal {
+            let universal = self.typechecker.universal_grants(&target.name);
+            grants.extend(universal.into_iter().map(|(origin, grant)| GrantRef {
+                grant,
+                origin: origin.unwrap_or(rule.origin),
+                fragment: origin.map_or(rule.fragment, |_| None),
+            }));
+        }
+
+        for decl in self.included_fragments(rule, target) {
+            grants.extend(decl.fragment.data.grants.iter().map(|grant| GrantRef {
+                grant,
+                origin: decl.origin,
+                fragment: Some(decl.fragment),
+            }));
+        }
+
+        grants
+    }
+
+    fn included_fragments(&self, rule: &RuleRef<'a>, target: &TcEntity) -> Vec<FragmentDecl<'a>> {
+        rule.rule
+            .data
+            .include_fragments
+            .iter()
+            .filter_map(|fragment| {
+                self.typechecker
+                    .resolve_fragment(rule.module, &fragment.data.value, &target.name)
+                    .ok()
+                    .copied()
+            })
+            .collect()
+    }
+}
+
+/// A rule matches an edge when the relationship names agree (or the rule is
+/// universal) and every attribute on the rule is present on the edge. Rule
+/// attributes without arguments match regardless of the edge's arguments.
+pub(crate) fn rule_matches(rule: &Rule, edge: &Edge) -> bool {
+    let relationship = &rule.relationship.data.value;
+    if relationship != "*" && relationship != &edge.relationship {
+        return false;
+    }
+
+    rule.attributes.iter().all(|attr| {
+        edge.attributes.iter().any(|edge_attr| {
+            edge_attr.name == attr.data.name.data.value
+                && (attr.data.arguments.is_empty() || attr.data.arguments == edge_attr.arguments)
+        })
+    })
+}
+
+fn dedup_rules<'a>(rules: impl Iterator<Item = RuleRef<'a>>) -> Vec<RuleRef<'a>> {
+    let mut seen = HashSet::new();
+
+    rules.filter(|rule| seen.insert(rule.key())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::config::RuuLangConfig,
+        evaluator::fact_store::{Fact, InMemoryFactStore},
+        workspace::workspace::Workspace,
+    };
+
+    const BASIC: &str = include_str!("../../../tests/basic/basic.ruu");
+
+    async fn basic() -> Workspace {
+        let root = PathBuf::from("/workspace");
+        let mut workspace = Workspace::new(RuuLangConfig::default(), root.clone());
+
+        workspace
+            .patch_file(&root.join("basic.ruu"), &BASIC.to_string())
+            .await
+            .unwrap();
+
+        workspace
+    }
+
+    fn node(value: &str) -> Node {
+        Node::parse(value).unwrap()
+    }
+
+    fn grant(value: &str) -> Vec<String> {
+        value.split('.').map(str::to_string).collect()
+    }
+
+    /// `alice` is an admin of `acme` and `bob` is not. `acme` and `globex`
+    /// are each other's supplier and customer, so following the credit apps
+    /// from `acme` leads back to it.
+    fn store() -> InMemoryFactStore {
+        let admin = || vec![EdgeAttribute::new("authorization", vec!["admin"])];
+        let mut store = InMemoryFactStore::new();
+
+        for (subject, edge, object) in [
+            (
+                "User:alice",
+                Edge::new("personnel", admin()),
+                "Company:acme",
+            ),
+            ("User:bob", Edge::new("personnel", vec![]), "Company:acme"),
+            (
+                "Company:acme",
+                Edge::new("supplier-app", vec![]),
+                "CreditApp:first",
+            ),
+            (
+                "CreditApp:first",
+                Edge::new("customer", vec![]),
+                "Company:globex",
+            ),
+            (
+                "Company:globex",
+                Edge::new("customer-app", vec![]),
+                "CreditApp:second",
+            ),
+            (
+                "CreditApp:second",
+                Edge::new("supplier", vec![]),
+                "Company:acme",
+            ),
+        ] {
+            store.add(Fact::new(node(subject), edge, node(object)));
+        }
+
+        store
+    }
+
+    #[tokio::test]
+    async fn grants_what_a_matching_rule_declares() {
+        let workspace = basic().await;
+        let evaluator = workspace.evaluator();
+        let store = store();
+
+        let can = |subject, value, object| {
+            evaluator
+                .can(&store, &node(subject), &grant(value), &node(object))
+                .unwrap()
+        };
+
+        assert!(can("User:alice", "write", "Company:acme"));
+        assert!(can("User:bob", "read.basic", "Company:acme"));
+        assert!(!can("User:bob", "write", "Company:acme"));
+        assert!(!can("User:bob", "read", "Company:acme"));
+    }
+
+    #[tokio::test]
+    async fn recursive_fragments_terminate() {
+        let workspace = basic().await;
+        let evaluator = workspace.evaluator();
+        let store = store();
+        let alice = node("User:alice");
+
+        // Reached through `#basic-credit-app` including itself
+        assert!(evaluator
+            .can(&store, &alice, &grant("read"), &node("CreditApp:second"))
+            .unwrap());
+        assert!(!evaluator
+            .can(&store, &alice, &grant("read"), &node("CreditApp:unknown"))
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn queries_walk_both_ways() {
+        let workspace = basic().await;
+        let evaluator = workspace.evaluator();
+        let store = store();
+
+        let mut objects = evaluator
+            .reachable_objects(&store, &node("User:alice"), &grant("read"))
+            .unwrap()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        objects.sort();
+        assert_eq!(
+            objects,
+            [
+                "Company:acme",
+                "Company:globex",
+                "CreditApp:first",
+                "CreditApp:second"
+            ]
+        );
+
+        let subjects = evaluator
+            .subjects_with(&store, &grant("read"), &node("CreditApp:second"))
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(subjects, [node("User:alice")]);
+    }
+}
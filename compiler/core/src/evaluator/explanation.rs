@@ -0,0 +1,86 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::parser::{
+    parse_location::{DescendableChildren, Parsed},
+    ruulang_ast::Grant,
+};
+
+use super::fact_store::Fact;
+
+/// Points back at the `.ruu` source that produced part of a decision.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SourceRef {
+    pub origin: PathBuf,
+    pub loc: Option<(usize, usize)>,
+}
+
+impl SourceRef {
+    pub fn new<T: for<'a> DescendableChildren<'a>>(origin: &Path, parsed: &Parsed<T>) -> Self {
+        Self {
+            origin: origin.to_path_buf(),
+            loc: parsed.loc,
+        }
+    }
+}
+
+impl Display for SourceRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.loc {
+            Some((start, end)) => write!(f, "{}[{}..{}]", self.origin.display(), start, end),
+            None => write!(f, "{}", self.origin.display()),
+        }
+    }
+}
+
+/// A rule attribute together with the edge attribute it matched.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttributeMatch {
+    pub source: SourceRef,
+    pub name: String,
+    pub arguments: Vec<String>,
+}
+
+/// One traversed fact and the rule that matched it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExplanationStep {
+    pub fact: Fact,
+    pub rule: SourceRef,
+
+    /// The fragment whose body contributed the rule, if any.
+    pub fragment: Option<SourceRef>,
+    pub attributes: Vec<AttributeMatch>,
+}
+
+/// The grant that decided an access check.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GrantSource {
+    pub grant: Grant,
+    pub source: SourceRef,
+
+    /// The fragment the grant was included from, if any.
+    pub fragment: Option<SourceRef>,
+}
+
+/// The chain of rules from an entrypoint to the object of an access check.
+///
+/// For a successful check `grant` names the grant that allowed access. For a
+/// failed check the explanation describes the closest near-miss, `grant` is
+/// `None` and `denial` names the negated grant that withheld access, if any.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Explanation {
+    pub entrypoint: SourceRef,
+    pub steps: Vec<ExplanationStep>,
+    pub grant: Option<GrantSource>,
+    pub denial: Option<GrantSource>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccessDecision {
+    pub allowed: bool,
+    pub explanation: Option<Explanation>,
+}
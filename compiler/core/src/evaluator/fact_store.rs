@@ -0,0 +1,107 @@
+use std::{collections::HashMap, fmt::Display};
+
+use serde::Serialize;
+
+use super::evaluator::Edge;
+
+/// A concrete instance of an entity, e.g. `User:alice`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Node {
+    pub entity: String,
+    pub id: String,
+}
+
+impl Node {
+    pub fn new(entity: &str, id: &str) -> Self {
+        Self {
+            entity: entity.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    /// Parses the `Entity:id` shorthand.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (entity, id) = value.split_once(':')?;
+        Some(Self::new(entity, id))
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.entity, self.id)
+    }
+}
+
+/// A relationship between two nodes, e.g.
+/// `User:alice -personnel:authorization(admin)-> Company:acme`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Fact {
+    pub subject: Node,
+    pub edge: Edge,
+    pub object: Node,
+}
+
+impl Fact {
+    pub fn new(subject: Node, edge: Edge, object: Node) -> Self {
+        Self {
+            subject,
+            edge,
+            object,
+        }
+    }
+}
+
+pub trait FactStore {
+    /// All facts whose subject is `node`.
+    fn outgoing<'s>(&'s self, node: &Node) -> Box<dyn Iterator<Item = &'s Fact> + 's>;
+
+    /// All facts whose object is `node`.
+    fn incoming<'s>(&'s self, node: &Node) -> Box<dyn Iterator<Item = &'s Fact> + 's>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryFactStore {
+    facts: Vec<Fact>,
+    outgoing: HashMap<Node, Vec<usize>>,
+    incoming: HashMap<Node, Vec<usize>>,
+}
+
+impl InMemoryFactStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, fact: Fact) {
+        let index = self.facts.len();
+
+        self.outgoing
+            .entry(fact.subject.clone())
+            .or_default()
+            .push(index);
+        self.incoming
+            .entry(fact.object.clone())
+            .or_default()
+            .push(index);
+        self.facts.push(fact);
+    }
+
+    pub fn len(&self) -> usize {
+        self.facts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.facts.is_empty()
+    }
+}
+
+impl FactStore for InMemoryFactStore {
+    fn outgoing<'s>(&'s self, node: &Node) -> Box<dyn Iterator<Item = &'s Fact> + 's> {
+        let indices = self.outgoing.get(node).map_or(&[][..], |x| x.as_slice());
+        Box::new(indices.iter().map(|index| &self.facts[*index]))
+    }
+
+    fn incoming<'s>(&'s self, node: &Node) -> Box<dyn Iterator<Item = &'s Fact> + 's> {
+        let indices = self.incoming.get(node).map_or(&[][..], |x| x.as_slice());
+        Box::new(indices.iter().map(|index| &self.facts[*index]))
+    }
+}
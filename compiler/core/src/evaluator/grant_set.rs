@@ -0,0 +1,105 @@
+use crate::{parser::ruulang_ast::Grant, typechecker::tc_ast::TcEntity, utils::trie::Trie};
+
+/// The grants conferred by a single matched rule, together with the grants
+/// it withholds, both expanded to everything they imply.
+#[derive(Debug, Clone)]
+struct GrantScope {
+    allowed: Trie<String, Grant>,
+    denied: Trie<String, Grant>,
+
+    /// Whether a grant covers every grant beneath it, for entities that
+    /// don't declare how their grants imply each other
+    by_prefix: bool,
+}
+
+impl GrantScope {
+    fn covers(&self, grants: &Trie<String, Grant>, grant: &Vec<String>) -> bool {
+        match self.by_prefix {
+            true => grants.contains_suffix(grant),
+            false => grants.contains(grant),
+        }
+    }
+
+    fn allows(&self, grant: &Vec<String>) -> bool {
+        self.covers(&self.allowed, grant) && !self.covers(&self.denied, grant)
+    }
+}
+
+/// The grants held on an entity after evaluating a policy.
+///
+/// Grants follow the lattice declared by the entity: holding `read` also
+/// allows `read.basic` when the entity declares `read implies read.basic;`
+/// or `abstract read;`, or when it declares no lattice at all. Holding
+/// `read.basic` never allows `read`.
+///
+/// A negated grant only applies to the rule that declares it (and the
+/// fragments that rule includes). It never revokes a grant conferred by a
+/// different rule, so a grant is allowed as soon as any one rule allows it.
+#[derive(Debug, Clone)]
+pub struct GrantSet {
+    grants: Vec<Grant>,
+    scopes: Vec<GrantScope>,
+}
+
+impl GrantSet {
+    pub fn new() -> Self {
+        Self {
+            grants: vec![],
+            scopes: vec![],
+        }
+    }
+
+    /// Adds the grants conferred by one matched rule on `entity`. Negated
+    /// grants only withhold the grants passed in the same call.
+    pub(crate) fn extend<'a>(
+        &mut self,
+        entity: &TcEntity,
+        grants: impl IntoIterator<Item = &'a Grant>,
+    ) {
+        let mut scope = GrantScope {
+            allowed: Trie::new(),
+            denied: Trie::new(),
+            by_prefix: !entity.declares_lattice(),
+        };
+
+        for grant in grants {
+            let trie = match grant.negated {
+                true => &mut scope.denied,
+                false => &mut scope.allowed,
+            };
+            for implied in entity.implied_grants(&grant.grant) {
+                trie.add(&implied, grant.clone());
+            }
+
+            if !self.grants.contains(grant) {
+                self.grants.push(grant.clone());
+            }
+        }
+
+        self.scopes.push(scope);
+    }
+
+    pub fn allows(&self, grant: &[String]) -> bool {
+        let grant = grant.to_vec();
+        self.scopes.iter().any(|scope| scope.allows(&grant))
+    }
+
+    /// Every grant added to the set, negated grants included.
+    pub fn iter(&self) -> impl Iterator<Item = &Grant> {
+        self.grants.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.grants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grants.is_empty()
+    }
+}
+
+impl Default for GrantSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::parser::ruulang_ast::Rule;
+
+use super::{
+    evaluator::{rule_matches, Evaluator, RuleRef},
+    fact_store::{Fact, FactStore, Node},
+};
+
+/// A rule that matched the fact leading to `node`.
+#[derive(Debug, Clone)]
+pub(crate) struct Visit<'a> {
+    pub index: usize,
+    pub node: Node,
+    pub rule: RuleRef<'a>,
+}
+
+/// A traversed fact, the rule that matched it and the step it came from.
+#[derive(Debug, Clone)]
+pub(crate) struct Step<'a> {
+    pub fact: Fact,
+    pub rule: RuleRef<'a>,
+    pub parent: Option<usize>,
+}
+
+/// A lazy breadth-first walk of the entrypoint rules over a fact store.
+///
+/// Every `(node, rule)` pair is visited at most once, which is what stops
+/// recursive fragments (and universal rules) from looping forever.
+pub(crate) struct Walk<'e, 'a, S: FactStore + ?Sized> {
+    evaluator: &'e Evaluator<'a>,
+    store: &'e S,
+
+    steps: Vec<Step<'a>>,
+    queue: VecDeque<usize>,
+    visited: HashSet<(Node, *const Rule)>,
+}
+
+impl<'e, 'a, S: FactStore + ?Sized> Walk<'e, 'a, S> {
+    pub fn new(evaluator: &'e Evaluator<'a>, store: &'e S, subject: &Node) -> Self {
+        let mut walk = Self {
+            evaluator,
+            store,
+            steps: vec![],
+            queue: VecDeque::new(),
+            visited: HashSet::new(),
+        };
+
+        let rules = evaluator.entrypoint_rules(&subject.entity);
+        walk.enqueue_matches(subject, rules, None);
+        walk
+    }
+
+    /// The steps leading from the entrypoint to `index`, in order.
+    pub fn chain(&self, index: usize) -> Vec<&Step<'a>> {
+        let mut chain = vec![];
+        let mut current = Some(index);
+
+        while let Some(index) = current {
+            let step = &self.steps[index];
+            chain.push(step);
+            current = step.parent;
+        }
+
+        chain.reverse();
+        chain
+    }
+
+    pub fn depth(&self, index: usize) -> usize {
+        self.chain(index).len()
+    }
+
+    fn enqueue_matches(&mut self, from: &Node, rules: Vec<RuleRef<'a>>, parent: Option<usize>) {
+        for fact in self.store.outgoing(from) {
+            for rule in &rules {
+                if !rule_matches(rule.rule, &fact.edge) {
+                    continue;
+                }
+
+                let key = (fact.object.clone(), rule.key());
+                if self.visited.insert(key) {
+                    self.steps.push(Step {
+                        fact: fact.clone(),
+                        rule: *rule,
+                        parent,
+                    });
+                    self.queue.push_back(self.steps.len() - 1);
+                }
+            }
+        }
+    }
+}
+
+impl<'e, 'a, S: FactStore + ?Sized> Iterator for Walk<'e, 'a, S> {
+    type Item = Visit<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.queue.pop_front()?;
+        let step = &self.steps[index];
+        let visit = Visit {
+            index,
+            node: step.fact.object.clone(),
+            rule: step.rule,
+        };
+
+        if let Ok(entity) = self.evaluator.entity(&visit.node.entity) {
+            let children = self.evaluator.child_rules(&visit.rule, entity);
+            self.enqueue_matches(&visit.node, children, Some(index));
+        }
+
+        Some(visit)
+    }
+}
+
+/// Where a rule can be reached from.
+#[derive(Debug, Clone)]
+pub(crate) enum Parent<'a> {
+    /// A top-level rule of the entrypoint for the named entity.
+    Entrypoint(String),
+
+    /// A child of `rule`. When the child was pulled in from a fragment,
+    /// `target` names the entity `rule` has to land on for the fragment to
+    /// apply.
+    Rule {
+        rule: RuleRef<'a>,
+        target: Option<String>,
+    },
+}
+
+/// Every rule in the workspace, keyed by identity, with its parents.
+pub(crate) struct RuleGraph<'a> {
+    pub rules: Vec<RuleRef<'a>>,
+    pub parents: HashMap<*const Rule, Vec<Parent<'a>>>,
+}
+
+/// A lazy walk backwards from an object towards the subjects whose
+/// entrypoint rules can reach it.
+///
+/// Each state is a `(node, rule)` pair stating that `rule` has to match a
+/// fact arriving at `node`. Subjects are yielded as soon as an entrypoint is
+/// reached, and each subject is yielded once.
+pub(crate) struct ReverseWalk<'e, 'a, S: FactStore + ?Sized> {
+    store: &'e S,
+    graph: RuleGraph<'a>,
+
+    queue: VecDeque<(Node, RuleRef<'a>)>,
+    visited: HashSet<(Node, *const Rule)>,
+
+    pending: VecDeque<Node>,
+    yielded: HashSet<Node>,
+}
+
+impl<'e, 'a, S: FactStore + ?Sized> ReverseWalk<'e, 'a, S> {
+    /// Starts from every rule in `graph` for which `grants_object` holds.
+    pub fn new(
+        store: &'e S,
+        graph: RuleGraph<'a>,
+        object: &Node,
+        grants_object: impl Fn(&RuleRef<'a>) -> bool,
+    ) -> Self {
+        let mut walk = Self {
+            store,
+            graph,
+            queue: VecDeque::new(),
+            visited: HashSet::new(),
+            pending: VecDeque::new(),
+            yielded: HashSet::new(),
+        };
+
+        let roots = walk
+            .graph
+            .rules
+            .iter()
+            .filter(|rule| grants_object(rule))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for rule in roots {
+            walk.enqueue(object.clone(), rule);
+        }
+
+        walk
+    }
+
+    fn enqueue(&mut self, node: Node, rule: RuleRef<'a>) {
+        if self.visited.insert((node.clone(), rule.key())) {
+            self.queue.push_back((node, rule));
+        }
+    }
+
+    fn expand(&mut self, node: &Node, rule: &RuleRef<'a>) {
+        let parents = self
+            .graph
+            .parents
+            .get(&rule.key())
+            .cloned()
+            .unwrap_or_default();
+
+        let sources = self
+            .store
+            .incoming(node)
+            .filter(|fact| rule_matches(rule.rule, &fact.edge))
+            .map(|fact| fact.subject.clone())
+            .collect::<Vec<_>>();
+
+        for source in sources {
+            for parent in &parents {
+                match parent {
+                    Parent::Entrypoint(entity) => {
+                        if &source.entity == entity && self.yielded.insert(source.clone()) {
+                            self.pending.push_back(source.clone());
+                        }
+                    }
+
+                    Parent::Rule { rule, target } => {
+                        if target
+                            .as_ref()
+                            .map_or(true, |target| target == &source.entity)
+                        {
+                            self.enqueue(source.clone(), *rule);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'e, 'a, S: FactStore + ?Sized> Iterator for ReverseWalk<'e, 'a, S> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(subject) = self.pending.pop_front() {
+                return Some(subject);
+            }
+
+            let (node, rule) = self.queue.pop_front()?;
+            self.expand(&node, &rule);
+        }
+    }
+}
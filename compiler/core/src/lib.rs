@@ -16,18 +16,41 @@ pub mod config {
 }
 
 pub mod workspace {
+    pub mod completion;
+    pub mod rename;
+    pub mod symbols;
     pub mod workspace;
 }
 
 pub mod utils {
     pub mod error;
+    pub mod suggestions;
     pub mod trie;
     pub mod with_origin;
 }
 
 pub mod typechecker {
-    mod tc_ast;
+    pub mod modules;
+    pub(crate) mod recursion;
+    pub(crate) mod tc_ast;
     pub mod typechecker;
+    pub(crate) mod usage;
+}
+
+pub mod diagnostics {
+    pub mod diagnostic;
+    pub mod emitter;
+    pub mod renderer;
+}
+
+pub mod evaluator {
+    #[allow(clippy::module_inception)]
+    pub mod evaluator;
+    pub mod explanation;
+    pub mod fact_store;
+    pub mod grant_set;
+
+    mod search;
 }
 
 pub mod codegen {
@@ -8,13 +8,18 @@ pub trait ParserAssemble {
 
 impl ParserAssemble for Vec<ParserStatement> {
     fn assemble(&self) -> (RuuLangSchema, RuuLangFile) {
+        let mut imports = Vec::new();
         let mut fragments = Vec::new();
         let mut entrypoints = Vec::new();
         let mut entities = Vec::new();
+        let mut context = Vec::new();
 
         for statement in self {
             match statement {
                 ParserStatement::Comment(_) => {}
+                ParserStatement::Import(import) => {
+                    imports.push(import.clone());
+                }
                 ParserStatement::Fragment(fragment) => {
                     fragments.push(fragment.clone());
                 }
@@ -24,6 +29,9 @@ impl ParserAssemble for Vec<ParserStatement> {
                 ParserStatement::Entity(entity) => {
                     entities.push(entity.clone());
                 }
+                ParserStatement::Context(variables) => {
+                    context.extend(variables.iter().cloned());
+                }
             }
         }
 
@@ -31,9 +39,13 @@ impl ParserAssemble for Vec<ParserStatement> {
             entities: entities.clone(),
         };
         let file = RuuLangFile {
+            module: String::new(),
+            syntax_errors: Vec::new(),
+            imports,
             entrypoints,
             fragments,
             entities,
+            context,
         };
 
         (schema, file)
@@ -9,8 +9,9 @@ use std::{
 use serde::{Deserialize, Serialize};
 
 use super::{
-    ruulang_ast::{Attribute, Entrypoint, Fragment, Grant, Rule},
-    schema_ast::{Entity, Relationship},
+    parser_utils::{parse_allowed_lints, parse_docstrings},
+    ruulang_ast::{Attribute, Condition, Entrypoint, Fragment, Grant, Import, Rule},
+    schema_ast::{ContextVariable, Entity, Relationship},
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -23,6 +24,9 @@ pub enum Context<'a> {
     Relationship(&'a Relationship),
     Entity(&'a Entity),
     Fragment(&'a Fragment),
+    Import(&'a Import),
+    Condition(&'a Condition),
+    ContextVariable(&'a ContextVariable),
     Identifier(&'a Identifier),
 }
 
@@ -32,14 +36,23 @@ pub struct DescentContext<'a> {
     pub name: Option<String>,
 
     pub docstring: &'a Option<String>,
+
+    /// Lints suppressed by `// ruu-allow(...)` comments on this node
+    pub allowed_lints: &'a [String],
 }
 
 impl<'a> DescentContext<'a> {
-    pub fn new(context: Context<'a>, name: Option<String>, docstring: &'a Option<String>) -> Self {
+    pub fn new(
+        context: Context<'a>,
+        name: Option<String>,
+        docstring: &'a Option<String>,
+        allowed_lints: &'a [String],
+    ) -> Self {
         Self {
             context,
             name,
             docstring,
+            allowed_lints,
         }
     }
 }
@@ -82,6 +95,11 @@ where
     pub loc: Option<(usize, usize)>,
     pub file_name: Option<PathBuf>,
     pub docstring: Option<String>,
+
+    /// Lints suppressed by `// ruu-allow(...)` comments before the node
+    #[serde(default)]
+    pub allowed_lints: Vec<String>,
+
     pub data: T,
 }
 
@@ -99,6 +117,7 @@ where
             loc,
             file_name,
             docstring,
+            allowed_lints: vec![],
             data,
         }
     }
@@ -108,6 +127,7 @@ where
             loc: Some(loc),
             file_name: None,
             docstring: None,
+            allowed_lints: vec![],
             data,
         }
     }
@@ -117,6 +137,7 @@ where
             loc: self.loc,
             file_name: self.file_name.clone(),
             docstring: self.docstring.clone(),
+            allowed_lints: self.allowed_lints.clone(),
             data: new_data,
         }
     }
@@ -127,6 +148,7 @@ where
             loc: self.loc,
             file_name: self.file_name,
             docstring: self.docstring,
+            allowed_lints: self.allowed_lints,
             data: new_data,
         };
 
@@ -139,6 +161,7 @@ where
             loc: self.loc,
             file_name: Some(new_filename),
             docstring: self.docstring,
+            allowed_lints: self.allowed_lints,
             data: self.data,
         };
 
@@ -151,11 +174,22 @@ where
             loc: self.loc,
             file_name: self.file_name,
             docstring: new_docstring,
+            allowed_lints: self.allowed_lints,
             data: self.data,
         };
 
         (new_parsed, old_docstring)
     }
+
+    /// Attaches the comments written before a node: block comments become
+    /// its docstring, and `// ruu-allow(...)` comments suppress lints.
+    pub fn into_with_comments(self, comments: Vec<String>) -> Parsed<T> {
+        let allowed_lints = parse_allowed_lints(&comments);
+        let (mut new_parsed, _) = self.into_with_docstring(parse_docstrings(comments));
+        new_parsed.allowed_lints = allowed_lints;
+
+        new_parsed
+    }
 }
 
 impl<T> Descendable for Parsed<T>
@@ -166,7 +200,12 @@ where
         if let Some((start, end)) = self.loc {
             if loc.0 >= start && loc.0 <= end {
                 let (context, name) = self.data.context_and_name();
-                let mut result = vec![DescentContext::new(context, name, &self.docstring)];
+                let mut result = vec![DescentContext::new(
+                    context,
+                    name,
+                    &self.docstring,
+                    &self.allowed_lints,
+                )];
 
                 let children = self.data.descend();
                 for child in children {
@@ -240,6 +279,8 @@ pub enum IdentifierKind {
     Fragment,
     Rule,
     Attribute,
+    Module,
+    Context,
 }
 
 #[derive(Debug, Clone, PartialEq)]
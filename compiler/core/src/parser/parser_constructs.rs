@@ -1,21 +1,70 @@
-use lalrpop_util::{lexer::Token, ParseError};
-
 use super::{
     parse_location::Parsed,
-    ruulang_ast::{Entrypoint, Fragment},
-    schema_ast::Entity,
+    ruulang_ast::{Entrypoint, Fragment, Import},
+    schema_ast::{ContextVariable, Entity},
 };
-use crate::ruulang::TermParser;
+use crate::{ruulang::TermParser, utils::error::SyntaxError};
 
 pub enum ParserStatement {
     Comment(String),
+    Import(Parsed<Import>),
     Fragment(Parsed<Fragment>),
     Entrypoint(Parsed<Entrypoint>),
     Entity(Parsed<Entity>),
+    Context(Vec<Parsed<ContextVariable>>),
 }
 
 impl ParserStatement {
-    pub fn parse(input: &str) -> Result<Vec<Self>, ParseError<usize, Token<'_>, &'static str>> {
-        TermParser::new().parse(input)
+    /// Parses the statements in `input`, skipping past any statement, rule,
+    /// or relationship with a syntax error. Returns the statements that could
+    /// be parsed along with the errors that were recovered from.
+    pub fn parse(input: &str) -> Result<(Vec<Self>, Vec<SyntaxError>), SyntaxError> {
+        let mut recovered = vec![];
+        let statements = TermParser::new()
+            .parse(&mut recovered, input)
+            .map_err(|error| {
+                let mut error = SyntaxError::from(error);
+
+                // The lexer doesn't report the character it failed on
+                if error.found.is_none() {
+                    if let Some(c) = input.get(error.loc.0..).and_then(|x| x.chars().next()) {
+                        error.found = Some(c.to_string());
+                        error.loc.1 = error.loc.0 + c.len_utf8();
+                    }
+                }
+
+                error
+            })?;
+
+        let mut errors = Vec::<SyntaxError>::new();
+        let mut last_end = None;
+
+        for recovery in recovered {
+            let mut error = SyntaxError::from(recovery.error);
+            let end = recovery
+                .dropped_tokens
+                .last()
+                .map_or(error.loc.1, |(_, _, end)| *end)
+                .max(error.loc.1);
+
+            // An error that directly follows the one before it was found while
+            // resuming from it, where what the parser expects says little about
+            // what was meant, so only the first error of a region lists it
+            let resumed = last_end.is_some_and(|last_end: usize| {
+                input
+                    .get(last_end..error.loc.0)
+                    .is_some_and(|between| between.trim().is_empty())
+            });
+            if resumed {
+                error.expected.clear();
+            }
+
+            last_end = Some(end);
+            errors.push(error);
+        }
+
+        errors.sort_by_key(|error| error.loc);
+
+        Ok((statements, errors))
     }
 }
@@ -63,3 +63,22 @@ pub fn parse_docstrings(inputs: Vec<String>) -> Option<String> {
         Some(results.join("\n"))
     }
 }
+
+/// Finds the lints named by `// ruu-allow(lint, ...)` line comments.
+pub fn parse_allowed_lints(inputs: &[String]) -> Vec<String> {
+    inputs
+        .iter()
+        .filter_map(|input| {
+            input
+                .trim()
+                .strip_prefix("//")?
+                .trim()
+                .strip_prefix("ruu-allow(")?
+                .trim_end()
+                .strip_suffix(')')
+        })
+        .flat_map(|lints| lints.split(','))
+        .map(|lint| lint.trim().to_string())
+        .filter(|lint| !lint.is_empty())
+        .collect()
+}
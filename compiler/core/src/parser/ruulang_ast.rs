@@ -1,17 +1,19 @@
 use std::{
+    cmp::Ordering,
     fmt::Display,
     hash::{Hash, Hasher},
     ops::Deref,
 };
 
-use serde::Serialize;
+use serde::{ser::SerializeMap, Serialize};
 
 use super::{
     parse_location::{
         Context, Descendable, DescendableChildren, DescentContext, Identifier, Parsed,
     },
-    schema_ast::Entity,
+    schema_ast::{ContextVariable, Entity},
 };
+use crate::utils::error::SyntaxError;
 
 pub trait RuuLangSerialize {
     fn ruulang_serialize(&self, indent: usize) -> String;
@@ -20,11 +22,46 @@ pub trait RuuLangSerialize {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Grant {
     pub grant: Vec<String>,
+
+    /// A negated grant (`!read.secret;`) withholds the grant, and everything
+    /// it implies, from the other grants conferred by the same rule.
+    pub negated: bool,
+
+    /// The grants an entity's declaration confers along with this one
+    /// (`read implies read.basic, read.secret;`).
+    pub implies: Vec<Parsed<Grant>>,
+
+    /// An abstract declaration (`abstract read;`) confers every grant
+    /// declared beneath it, e.g. `read.basic`.
+    pub is_abstract: bool,
 }
 
 impl Grant {
     pub fn new(grant: Vec<String>) -> Self {
-        Grant { grant }
+        Grant {
+            grant,
+            negated: false,
+            implies: vec![],
+            is_abstract: false,
+        }
+    }
+
+    pub fn denial(grant: Vec<String>) -> Self {
+        Grant {
+            grant,
+            negated: true,
+            implies: vec![],
+            is_abstract: false,
+        }
+    }
+
+    pub fn declaration(grant: Vec<String>, is_abstract: bool, implies: Vec<Parsed<Grant>>) -> Self {
+        Grant {
+            grant,
+            negated: false,
+            implies,
+            is_abstract,
+        }
     }
 }
 
@@ -38,6 +75,10 @@ impl Deref for Grant {
 
 impl Display for Grant {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.negated {
+            write!(f, "!")?;
+        }
+
         self.grant.join(".").fmt(f)
     }
 }
@@ -45,6 +86,9 @@ impl Display for Grant {
 impl Hash for Grant {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.grant.hash(state);
+        self.negated.hash(state);
+        self.implies.hash(state);
+        self.is_abstract.hash(state);
     }
 }
 
@@ -53,6 +97,21 @@ impl Serialize for Grant {
     where
         S: serde::Serializer,
     {
+        if self.negated {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry("deny", &self.grant)?;
+            return map.end();
+        }
+
+        // Declarations that take part in the grant lattice spell it out
+        if self.is_abstract || !self.implies.is_empty() {
+            let mut map = serializer.serialize_map(Some(3))?;
+            map.serialize_entry("grant", &self.grant)?;
+            map.serialize_entry("implies", &self.implies)?;
+            map.serialize_entry("abstract", &self.is_abstract)?;
+            return map.end();
+        }
+
         self.grant.serialize(serializer)
     }
 }
@@ -63,14 +122,94 @@ impl<'a> DescendableChildren<'a> for Grant {
     }
 
     fn descend(&'a self) -> Vec<&dyn Descendable> {
-        vec![]
+        self.implies.iter().map(|x| x as &dyn Descendable).collect()
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+/// The type of a single argument, as declared on a schema relationship.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributeParameter {
+    Int,
+    String,
+    OneOf(Vec<String>),
+}
+
+impl AttributeParameter {
+    /// `int` and `string` name a kind. Anything else is an enumeration of
+    /// the allowed values, e.g. `basic | admin`.
+    pub fn from_alternatives(alternatives: Vec<String>) -> Self {
+        match alternatives.as_slice() {
+            [kind] if kind == "int" => AttributeParameter::Int,
+            [kind] if kind == "string" => AttributeParameter::String,
+            _ => AttributeParameter::OneOf(alternatives),
+        }
+    }
+
+    pub fn accepts(&self, argument: &str) -> bool {
+        match self {
+            AttributeParameter::Int => argument.parse::<i64>().is_ok(),
+            AttributeParameter::String => true,
+            AttributeParameter::OneOf(values) => values.iter().any(|x| x == argument),
+        }
+    }
+}
+
+impl Display for AttributeParameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeParameter::Int => "int".fmt(f),
+            AttributeParameter::String => "string".fmt(f),
+            AttributeParameter::OneOf(values) => values.join(" | ").fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Attribute {
     pub name: Parsed<Identifier>,
+
+    /// The arguments as written. Schema relationships list their parameters
+    /// here too, as they are written in the declaration.
     pub arguments: Vec<String>,
+
+    /// The declared argument types. Only set on schema relationships, and
+    /// `None` when the schema leaves the arguments unchecked.
+    pub parameters: Option<Vec<AttributeParameter>>,
+
+    /// On rules, the types the schema declares for the arguments. They are
+    /// filled in before code is emitted, so `int` arguments can be emitted
+    /// as numbers.
+    pub argument_types: Option<Vec<AttributeParameter>>,
+}
+
+impl Serialize for Attribute {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let arguments = self
+            .arguments
+            .iter()
+            .enumerate()
+            .map(|(i, argument)| {
+                let kind = self.argument_types.as_ref().and_then(|x| x.get(i));
+
+                match (kind, argument.parse::<i64>()) {
+                    (Some(AttributeParameter::Int), Ok(value)) => ContextValue::Int(value),
+                    _ => ContextValue::String(argument.clone()),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("name", &self.name)?;
+        map.serialize_entry("arguments", &arguments)?;
+        if let Some(parameters) = &self.parameters {
+            map.serialize_entry("parameters", parameters)?;
+        }
+        map.end()
+    }
 }
 
 impl Hash for Attribute {
@@ -117,6 +256,137 @@ impl<'a> DescendableChildren<'a> for Attribute {
     }
 }
 
+/// A value of the runtime context, as written in a rule's condition or
+/// supplied when evaluating it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(untagged)]
+pub enum ContextValue {
+    Bool(bool),
+    Int(i64),
+    String(String),
+}
+
+impl ContextValue {
+    /// `true` and `false` are booleans and (optionally signed) digits are
+    /// integers. Anything else is a string.
+    pub fn from_literal(literal: String) -> Self {
+        match literal.as_str() {
+            "true" => ContextValue::Bool(true),
+            "false" => ContextValue::Bool(false),
+            _ => match literal.parse::<i64>() {
+                Ok(value) => ContextValue::Int(value),
+                Err(_) => ContextValue::String(literal),
+            },
+        }
+    }
+}
+
+impl Display for ContextValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContextValue::Bool(value) => value.fmt(f),
+            ContextValue::Int(value) => value.fmt(f),
+            ContextValue::String(value) => value.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum ConditionOperator {
+    #[serde(rename = "==")]
+    Eq,
+    #[serde(rename = "!=")]
+    Ne,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "<=")]
+    Le,
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = ">=")]
+    Ge,
+}
+
+impl ConditionOperator {
+    /// Whether the operator compares by order, and so only applies to `int`
+    /// variables.
+    pub fn is_ordering(&self) -> bool {
+        !matches!(self, ConditionOperator::Eq | ConditionOperator::Ne)
+    }
+
+    pub fn test(&self, ordering: Ordering) -> bool {
+        match self {
+            ConditionOperator::Eq => ordering.is_eq(),
+            ConditionOperator::Ne => ordering.is_ne(),
+            ConditionOperator::Lt => ordering.is_lt(),
+            ConditionOperator::Le => ordering.is_le(),
+            ConditionOperator::Gt => ordering.is_gt(),
+            ConditionOperator::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+impl Display for ConditionOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConditionOperator::Eq => "==",
+            ConditionOperator::Ne => "!=",
+            ConditionOperator::Lt => "<",
+            ConditionOperator::Le => "<=",
+            ConditionOperator::Gt => ">",
+            ConditionOperator::Ge => ">=",
+        }
+        .fmt(f)
+    }
+}
+
+/// A comparison of a context variable against a value, e.g. `hour >= 9`.
+/// A bare `mfa` is read as `mfa == true`, and `!mfa` as `mfa == false`.
+#[derive(Debug, Clone, PartialEq, Hash, Serialize)]
+pub struct Condition {
+    pub variable: Parsed<Identifier>,
+    pub operator: ConditionOperator,
+    pub value: ContextValue,
+}
+
+impl Condition {
+    /// Whether the condition holds when its variable is `value`. A value of
+    /// a different type never satisfies it.
+    pub fn holds(&self, value: &ContextValue) -> bool {
+        match (value, &self.value) {
+            (ContextValue::Int(value), ContextValue::Int(expected)) => {
+                self.operator.test(value.cmp(expected))
+            }
+            (ContextValue::Bool(_), ContextValue::Bool(_))
+            | (ContextValue::String(_), ContextValue::String(_)) => match self.operator {
+                ConditionOperator::Eq => value == &self.value,
+                ConditionOperator::Ne => value != &self.value,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.variable.data, self.operator, self.value)
+    }
+}
+
+impl<'a> DescendableChildren<'a> for Condition {
+    fn context_and_name(&'a self) -> (Context<'a>, Option<String>) {
+        (
+            Context::Condition(self),
+            Some(self.variable.data.value.clone()),
+        )
+    }
+
+    fn descend(&self) -> Vec<&dyn Descendable> {
+        vec![&self.variable as &dyn Descendable]
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Rule {
     pub relationship: Parsed<Identifier>,
@@ -126,6 +396,16 @@ pub struct Rule {
 
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub include_fragments: Vec<Parsed<Identifier>>,
+
+    /// The conditions on the runtime context that must all hold for the rule
+    /// to apply (`employee if mfa and hour >= 9 { ... }`).
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub conditions: Vec<Parsed<Condition>>,
+
+    /// Whether the rule confers the universal grants of the entity it
+    /// reaches, as both rules written `rel { * }` do.
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub universal: bool,
 }
 
 impl Hash for Rule {
@@ -133,8 +413,10 @@ impl Hash for Rule {
         self.relationship.hash(state);
         self.attributes.hash(state);
         self.include_fragments.hash(state);
+        self.conditions.hash(state);
         self.grants.hash(state);
         self.rules.hash(state);
+        self.universal.hash(state);
     }
 }
 
@@ -154,15 +436,22 @@ impl RuuLangSerialize for Rule {
             }
         }
 
+        for (i, condition) in self.conditions.iter().enumerate() {
+            match i {
+                0 => result.push_str(" if "),
+                _ => result.push_str(" and "),
+            }
+            result.push_str(format!("{}", condition.data).as_str());
+        }
+
         result.push_str(" {");
 
         if self.grants.len() > 0 {
             result.push_str("\n");
 
             for grant in self.grants.iter() {
-                let grant_str = grant.data.join(".");
                 result.push_str(
-                    format!("{}{};\n", " ".repeat((indent + 1) * 4).as_str(), grant_str).as_str(),
+                    format!("{}{};\n", " ".repeat((indent + 1) * 4).as_str(), grant.data).as_str(),
                 );
             }
         }
@@ -214,11 +503,64 @@ impl<'a> DescendableChildren<'a> for Rule {
             .chain(self.grants.iter().map(|x| x as &dyn Descendable))
             .chain(self.rules.iter().map(|x| x as &dyn Descendable))
             .chain(self.include_fragments.iter().map(|x| x as &dyn Descendable))
+            .chain(self.conditions.iter().map(|x| x as &dyn Descendable))
             .chain(std::iter::once(&self.relationship as &dyn Descendable))
             .collect()
     }
 }
 
+/// `import billing::fragments;` or `import billing::fragments as billing;`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Import {
+    pub module: Parsed<Identifier>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub alias: Option<Parsed<Identifier>>,
+}
+
+impl Import {
+    /// The name the module is referred to by in the importing file. Without
+    /// an alias this is the last segment of the module path.
+    pub fn name(&self) -> &str {
+        match &self.alias {
+            Some(alias) => alias.data.value.as_str(),
+            None => self
+                .module
+                .data
+                .value
+                .rsplit("::")
+                .next()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl RuuLangSerialize for Import {
+    fn ruulang_serialize(&self, indent: usize) -> String {
+        match &self.alias {
+            Some(alias) => format!(
+                "{}import {} as {};\n",
+                " ".repeat(indent * 4),
+                self.module.data,
+                alias.data
+            ),
+            None => format!("{}import {};\n", " ".repeat(indent * 4), self.module.data),
+        }
+    }
+}
+
+impl<'a> DescendableChildren<'a> for Import {
+    fn context_and_name(&'a self) -> (Context<'a>, Option<String>) {
+        (Context::Import(self), Some(self.module.data.value.clone()))
+    }
+
+    fn descend(&self) -> Vec<&dyn Descendable> {
+        std::iter::once(&self.module as &dyn Descendable)
+            .chain(self.alias.iter().map(|x| x as &dyn Descendable))
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Entrypoint {
     pub entrypoint: Parsed<Identifier>,
@@ -275,6 +617,11 @@ pub struct Fragment {
     pub for_entity: Parsed<Identifier>,
     pub rules: Vec<Parsed<Rule>>,
     pub grants: Vec<Parsed<Grant>>,
+
+    /// Whether the fragment can include itself through its rules. Only known
+    /// once the whole workspace is typechecked, so it is set before emitting.
+    #[serde(default)]
+    pub recursive: bool,
 }
 
 impl Hash for Fragment {
@@ -295,9 +642,8 @@ impl RuuLangSerialize for Fragment {
             result.push_str("\n");
 
             for grant in self.grants.iter() {
-                let grant_str = grant.data.join(".");
                 result.push_str(
-                    format!("{}{};\n", " ".repeat((indent + 1) * 4).as_str(), grant_str).as_str(),
+                    format!("{}{};\n", " ".repeat((indent + 1) * 4).as_str(), grant.data).as_str(),
                 );
             }
         }
@@ -337,6 +683,19 @@ impl<'a> DescendableChildren<'a> for Fragment {
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct RuuLangFile {
+    /// The module this file declares, named after its path from the
+    /// workspace root, e.g. `billing::fragments`.
+    #[serde(skip)]
+    pub module: String,
+
+    /// Syntax errors that were recovered from while parsing. The file only
+    /// holds the statements that could be parsed.
+    #[serde(skip)]
+    pub syntax_errors: Vec<SyntaxError>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub imports: Vec<Parsed<Import>>,
+
     pub entrypoints: Vec<Parsed<Entrypoint>>,
 
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
@@ -344,6 +703,40 @@ pub struct RuuLangFile {
 
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub entities: Vec<Parsed<Entity>>,
+
+    /// The context variables declared by the file, which rules anywhere in
+    /// the workspace can be conditioned on.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub context: Vec<Parsed<ContextVariable>>,
+}
+
+impl RuuLangFile {
+    /// Whether the file imports a module or qualifies a reference with one.
+    /// Files that do neither share the global namespace.
+    pub fn uses_modules(&self) -> bool {
+        fn qualified(name: &Parsed<Identifier>) -> bool {
+            name.data.value.contains("::")
+        }
+
+        fn rule_uses_modules(rule: &Parsed<Rule>) -> bool {
+            rule.data.include_fragments.iter().any(qualified)
+                || rule.data.rules.iter().any(rule_uses_modules)
+        }
+
+        !self.imports.is_empty()
+            || self.entrypoints.iter().any(|x| {
+                qualified(&x.data.entrypoint) || x.data.rules.iter().any(rule_uses_modules)
+            })
+            || self.fragments.iter().any(|x| {
+                qualified(&x.data.for_entity) || x.data.rules.iter().any(rule_uses_modules)
+            })
+            || self.entities.iter().any(|x| {
+                x.data
+                    .relationships
+                    .iter()
+                    .any(|x| qualified(&x.data.entity_name))
+            })
+    }
 }
 
 impl Hash for RuuLangFile {
@@ -356,6 +749,14 @@ impl RuuLangSerialize for RuuLangFile {
     fn ruulang_serialize(&self, indent: usize) -> String {
         let mut result = String::new();
 
+        for import in self.imports.iter() {
+            result.push_str(import.data.ruulang_serialize(indent).as_str());
+        }
+
+        if !self.imports.is_empty() {
+            result.push('\n');
+        }
+
         for fragment in self.fragments.iter() {
             result.push_str(fragment.data.ruulang_serialize(indent).as_str());
             result.push_str("\n");
@@ -384,11 +785,13 @@ impl<'a> DescendableChildren<'a> for RuuLangFile {
     }
 
     fn descend(&self) -> Vec<&dyn Descendable> {
-        self.entrypoints
+        self.imports
             .iter()
             .map(|x| x as &dyn Descendable)
+            .chain(self.entrypoints.iter().map(|x| x as &dyn Descendable))
             .chain(self.fragments.iter().map(|x| x as &dyn Descendable))
             .chain(self.entities.iter().map(|x| x as &dyn Descendable))
+            .chain(self.context.iter().map(|x| x as &dyn Descendable))
             .collect()
     }
 }
@@ -400,7 +803,7 @@ impl Descendable for RuuLangFile {
     ) -> Option<Vec<super::parse_location::DescentContext>> {
         let children = self.descend();
 
-        let mut result = vec![DescentContext::new(Context::None, None, &None)];
+        let mut result = vec![DescentContext::new(Context::None, None, &None, &[])];
         for child in children {
             if let Some(mut ctx) = child.descend_at(loc) {
                 result.append(&mut ctx);
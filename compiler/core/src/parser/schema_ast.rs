@@ -1,8 +1,10 @@
+use std::fmt::Display;
+
 use serde::Serialize;
 
 use super::{
     parse_location::{Context, Descendable, DescendableChildren, Identifier, Parsed},
-    ruulang_ast::{Attribute, Grant},
+    ruulang_ast::{Attribute, ContextValue, Grant},
 };
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
@@ -15,6 +17,11 @@ pub struct Entity {
     pub name: Parsed<Identifier>,
     pub relationships: Vec<Parsed<Relationship>>,
     pub grants: Vec<Parsed<Grant>>,
+
+    /// The grants a universal rule confers on the entity (`universal read;`).
+    /// Universal rules grant `read` and `write` when this isn't declared.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub universal: Option<Vec<Parsed<Grant>>>,
 }
 
 impl<'a> DescendableChildren<'a> for Entity {
@@ -27,6 +34,12 @@ impl<'a> DescendableChildren<'a> for Entity {
             .iter()
             .map(|x| x as &dyn Descendable)
             .chain(self.grants.iter().map(|x| x as &dyn Descendable))
+            .chain(
+                self.universal
+                    .iter()
+                    .flatten()
+                    .map(|x| x as &dyn Descendable),
+            )
             .chain(std::iter::once(&self.name as &dyn Descendable))
             .collect()
     }
@@ -56,3 +69,69 @@ impl<'a> DescendableChildren<'a> for Relationship {
             .collect()
     }
 }
+
+/// The type of a context variable, as declared in a `context` block.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextType {
+    Bool,
+    Int,
+    String,
+    OneOf(Vec<String>),
+}
+
+impl ContextType {
+    /// `bool`, `int` and `string` name a kind. Anything else is an
+    /// enumeration of the allowed values, e.g. `us | eu`.
+    pub fn from_alternatives(alternatives: Vec<String>) -> Self {
+        match alternatives.as_slice() {
+            [kind] if kind == "bool" => ContextType::Bool,
+            [kind] if kind == "int" => ContextType::Int,
+            [kind] if kind == "string" => ContextType::String,
+            _ => ContextType::OneOf(alternatives),
+        }
+    }
+
+    pub fn accepts(&self, value: &ContextValue) -> bool {
+        match (self, value) {
+            (ContextType::Bool, ContextValue::Bool(_)) => true,
+            (ContextType::Int, ContextValue::Int(_)) => true,
+            (ContextType::String, ContextValue::String(_)) => true,
+            (ContextType::OneOf(values), value) => values.iter().any(|x| *x == value.to_string()),
+            _ => false,
+        }
+    }
+}
+
+impl Display for ContextType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContextType::Bool => "bool".fmt(f),
+            ContextType::Int => "int".fmt(f),
+            ContextType::String => "string".fmt(f),
+            ContextType::OneOf(values) => values.join(" | ").fmt(f),
+        }
+    }
+}
+
+/// A variable of the runtime context (`mfa: bool;`), declared in a
+/// `context { ... }` block, that rules can be conditioned on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContextVariable {
+    pub name: Parsed<Identifier>,
+    #[serde(rename = "type")]
+    pub kind: ContextType,
+}
+
+impl<'a> DescendableChildren<'a> for ContextVariable {
+    fn context_and_name(&'a self) -> (Context<'a>, Option<String>) {
+        (
+            Context::ContextVariable(self),
+            Some(self.name.data.value.clone()),
+        )
+    }
+
+    fn descend(&self) -> Vec<&dyn Descendable> {
+        vec![&self.name as &dyn Descendable]
+    }
+}
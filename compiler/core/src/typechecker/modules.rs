@@ -0,0 +1,230 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{
+    parser::{parse_location::Parsed, ruulang_ast::RuuLangFile, schema_ast::Entity},
+    utils::{error::Result, with_origin::WithOrigin},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolveError {
+    /// The qualifier of a reference does not name an imported module.
+    UnknownModule(String),
+
+    /// Nothing by that name is visible from the referencing module.
+    NotFound(String),
+
+    /// More than one module declares the name and none of them is preferred.
+    Ambiguous(String, Vec<String>),
+}
+
+/// Every `.ruu` file is a module named after its path from the workspace
+/// root, so `billing/fragments.ruu` declares `billing::fragments`.
+///
+/// A qualified reference like `billing::BasicData` names the module through
+/// one of the referencing file's imports. An unqualified reference prefers a
+/// declaration in the same module, then one in an imported module, and
+/// finally the only declaration in the workspace.
+///
+/// Files that neither import a module nor qualify a reference share the
+/// global namespace, as every file did before modules existed. Entities
+/// declared there by the same name are parts of one entity.
+#[derive(Debug, Clone)]
+pub struct ModuleIndex<'a> {
+    modules: HashMap<&'a PathBuf, &'a str>,
+    imports: HashMap<&'a str, HashMap<&'a str, &'a str>>,
+
+    /// The namespace of each module, which is either the module itself or
+    /// the global namespace, `""`
+    namespaces: HashMap<&'a str, &'a str>,
+
+    /// The namespaces declaring an entity, by name
+    entities: HashMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> ModuleIndex<'a> {
+    pub fn new(
+        entities: &'a Vec<WithOrigin<Parsed<Entity>>>,
+        schemas: &'a Vec<WithOrigin<Result<RuuLangFile>>>,
+    ) -> Self {
+        let mut modules = HashMap::new();
+        let mut imports = HashMap::<&str, HashMap<&str, &str>>::new();
+        let mut namespaces = HashMap::new();
+
+        for schema in schemas {
+            let Ok(file) = &schema.data else {
+                continue;
+            };
+
+            modules.insert(&schema.origin, file.module.as_str());
+            namespaces.insert(
+                file.module.as_str(),
+                match file.uses_modules() {
+                    true => file.module.as_str(),
+                    false => GLOBAL,
+                },
+            );
+
+            let aliases = imports.entry(file.module.as_str()).or_default();
+            for import in &file.imports {
+                aliases
+                    .entry(import.data.name())
+                    .or_insert(import.data.module.data.value.as_str());
+            }
+        }
+
+        let mut index = Self {
+            modules,
+            imports,
+            namespaces,
+            entities: HashMap::new(),
+        };
+
+        for entity in entities {
+            let namespace = index.namespace(index.module_of(&entity.origin));
+            let declared_in = index
+                .entities
+                .entry(entity.data.data.name.data.value.as_str())
+                .or_default();
+
+            if !declared_in.contains(&namespace) {
+                declared_in.push(namespace);
+            }
+        }
+
+        index
+    }
+
+    /// The module declared by the file at `origin`.
+    pub fn module_of(&self, origin: &PathBuf) -> &'a str {
+        self.modules.get(origin).copied().unwrap_or_default()
+    }
+
+    /// The file declaring `module`.
+    pub fn origin_of(&self, module: &str) -> Option<&'a PathBuf> {
+        self.modules
+            .iter()
+            .find(|(_, x)| **x == module)
+            .map(|(origin, _)| *origin)
+    }
+
+    pub fn contains_module(&self, module: &str) -> bool {
+        self.modules.values().any(|x| *x == module)
+    }
+
+    /// The namespace the declarations of `module` belong to.
+    pub fn namespace<'b>(&self, module: &'b str) -> &'b str
+    where
+        'a: 'b,
+    {
+        self.namespaces.get(module).copied().unwrap_or(module)
+    }
+
+    /// The name an entity is known by across the workspace. This is its
+    /// declared name, unless it's declared outside the global namespace and
+    /// several namespaces declare an entity by that name, in which case it
+    /// is qualified with its module.
+    pub fn entity_name(&self, module: &str, name: &str) -> String {
+        let namespace = self.namespace(module);
+
+        match self.entities.get(name) {
+            Some(declared_in) if declared_in.len() > 1 && namespace != GLOBAL => {
+                format!("{}::{}", namespace, name)
+            }
+            _ => name.to_string(),
+        }
+    }
+
+    /// Resolves an entity reference written in `from` to the entity's name
+    /// across the workspace.
+    pub fn resolve_entity(
+        &self,
+        from: &str,
+        reference: &str,
+    ) -> std::result::Result<String, ResolveError> {
+        let (_, name) = split_reference(reference);
+        let declared_in = self.entities.get(name).cloned().unwrap_or_default();
+        let module = self.resolve(from, reference, &declared_in)?;
+
+        Ok(self.entity_name(module, name))
+    }
+
+    /// Picks which of the modules in `declared_in` a reference written in
+    /// `from` refers to. Of several modules in the same namespace, the first
+    /// is picked.
+    pub fn resolve<'b>(
+        &self,
+        from: &str,
+        reference: &str,
+        declared_in: &[&'b str],
+    ) -> std::result::Result<&'b str, ResolveError> {
+        let (qualifier, name) = split_reference(reference);
+        let aliases = self.imports.get(from);
+        let in_namespace = |namespace: &str| {
+            declared_in
+                .iter()
+                .find(|x| self.namespace(x) == namespace)
+                .copied()
+        };
+
+        if let Some(qualifier) = qualifier {
+            let module = match aliases.and_then(|x| x.get(qualifier)) {
+                Some(module) => *module,
+                None if qualifier == from => from,
+                None => return Err(ResolveError::UnknownModule(qualifier.to_string())),
+            };
+
+            return in_namespace(self.namespace(module))
+                .ok_or_else(|| ResolveError::NotFound(reference.to_string()));
+        }
+
+        if let Some(module) = in_namespace(self.namespace(from)) {
+            return Ok(module);
+        }
+
+        // One module for each namespace declaring the name
+        let mut namespaces = declared_in
+            .iter()
+            .filter_map(|x| in_namespace(self.namespace(x)))
+            .collect::<Vec<_>>();
+        namespaces.sort();
+        namespaces.dedup();
+
+        let imported = namespaces
+            .iter()
+            .filter(|module| {
+                aliases.is_some_and(|x| {
+                    x.values()
+                        .any(|x| self.namespace(x) == self.namespace(module))
+                })
+            })
+            .copied()
+            .collect::<Vec<_>>();
+
+        let candidates = match imported.is_empty() {
+            true => namespaces,
+            false => imported,
+        };
+
+        match candidates.as_slice() {
+            [] => Err(ResolveError::NotFound(name.to_string())),
+            [module] => Ok(module),
+            _ => {
+                let mut modules = candidates.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+                modules.sort();
+
+                Err(ResolveError::Ambiguous(name.to_string(), modules))
+            }
+        }
+    }
+}
+
+/// The namespace shared by files that don't use modules.
+const GLOBAL: &str = "";
+
+/// Splits `billing::BasicData` into its module qualifier and name.
+pub fn split_reference(reference: &str) -> (Option<&str>, &str) {
+    match reference.rsplit_once("::") {
+        Some((qualifier, name)) => (Some(qualifier), name),
+        None => (None, reference),
+    }
+}
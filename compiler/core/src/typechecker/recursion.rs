@@ -0,0 +1,165 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::parser::{
+    parse_location::{Identifier, Parsed},
+    ruulang_ast::{Fragment, Rule},
+};
+
+use super::{
+    tc_ast::TcEntity,
+    typechecker::{fragment_id, FragmentId, Typechecker},
+};
+
+/// An include of one fragment from inside another, along with the
+/// relationships traversed from the including fragment to reach it.
+#[derive(Debug, Clone)]
+struct Include {
+    target: FragmentId,
+    relationships: Vec<String>,
+    fragment: Parsed<Identifier>,
+}
+
+/// How a recursive fragment reaches itself again.
+#[derive(Debug, Clone)]
+pub(crate) struct Cycle {
+    /// The relationships and fragment includes along the cycle, e.g.
+    /// `supplier -> #supplier-app -> customer -> #basic-credit-app`
+    pub steps: Vec<String>,
+
+    /// The include in the recursive fragment that starts the cycle
+    pub include: Parsed<Identifier>,
+}
+
+/// The fragments that include themselves, found by walking the graph of
+/// fragment includes across the workspace.
+///
+/// An include can only appear inside a rule, so every edge of the graph
+/// crosses at least one relationship and a fragment can never include itself
+/// directly.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Recursion {
+    cycles: HashMap<FragmentId, Cycle>,
+}
+
+impl Recursion {
+    pub fn new(typechecker: &Typechecker) -> Self {
+        let mut graph = HashMap::<FragmentId, Vec<Include>>::new();
+
+        for (entity, decl) in typechecker.fragments() {
+            let Some(entity) = typechecker.entity(entity) else {
+                continue;
+            };
+
+            let mut includes = vec![];
+            for rule in &decl.fragment.data.rules {
+                visit_rule(
+                    typechecker,
+                    decl.module,
+                    entity,
+                    rule,
+                    &mut vec![],
+                    &mut includes,
+                );
+            }
+
+            graph.insert(decl.id(), includes);
+        }
+
+        let cycles = graph
+            .keys()
+            .filter_map(|fragment| Some((fragment.clone(), find_cycle(&graph, fragment)?)))
+            .collect();
+
+        Recursion { cycles }
+    }
+
+    /// How the fragment declared in `module` reaches itself, if it does.
+    pub fn cycle(&self, module: &str, fragment: &Parsed<Fragment>) -> Option<&Cycle> {
+        self.cycles.get(&fragment_id(module, fragment))
+    }
+
+    pub fn is_recursive(&self, module: &str, fragment: &Parsed<Fragment>) -> bool {
+        self.cycle(module, fragment).is_some()
+    }
+}
+
+fn visit_rule(
+    typechecker: &Typechecker,
+    module: &str,
+    entity: &TcEntity,
+    rule: &Parsed<Rule>,
+    relationships: &mut Vec<String>,
+    includes: &mut Vec<Include>,
+) {
+    // Universal rules never include fragments
+    let Some(relationship) = entity.get_rule(&rule.data.relationship) else {
+        return;
+    };
+
+    let Some(target) = typechecker.entity(&relationship.data.entity_name.data.value) else {
+        return;
+    };
+
+    relationships.push(rule.data.relationship.data.value.clone());
+
+    for included in &rule.data.include_fragments {
+        if let Ok(decl) = typechecker.resolve_fragment(module, &included.data.value, &target.name) {
+            includes.push(Include {
+                target: decl.id(),
+                relationships: relationships.clone(),
+                fragment: included.clone(),
+            });
+        }
+    }
+
+    for child in &rule.data.rules {
+        visit_rule(typechecker, module, target, child, relationships, includes);
+    }
+
+    relationships.pop();
+}
+
+/// Finds the shortest chain of includes leading from `start` back to itself.
+fn find_cycle(graph: &HashMap<FragmentId, Vec<Include>>, start: &FragmentId) -> Option<Cycle> {
+    let mut previous = HashMap::<&FragmentId, (&FragmentId, &Include)>::new();
+    let mut seen = HashSet::new();
+    let mut pending = VecDeque::from([start]);
+
+    while let Some(current) = pending.pop_front() {
+        for include in graph.get(current).into_iter().flatten() {
+            if &include.target == start {
+                let mut chain = vec![include];
+                let mut node = current;
+                while node != start {
+                    let (parent, include) = previous[&node];
+                    chain.push(include);
+                    node = parent;
+                }
+                chain.reverse();
+
+                let steps = chain
+                    .iter()
+                    .flat_map(|include| {
+                        include
+                            .relationships
+                            .iter()
+                            .cloned()
+                            .chain(std::iter::once(format!("#{}", include.fragment.data.value)))
+                    })
+                    .collect();
+
+                return Some(Cycle {
+                    steps,
+                    include: chain[0].fragment.clone(),
+                });
+            }
+
+            if seen.insert(&include.target) {
+                previous.insert(&include.target, (current, include));
+                pending.push_back(&include.target);
+            }
+        }
+    }
+
+    None
+}
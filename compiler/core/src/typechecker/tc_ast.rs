@@ -1,16 +1,49 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
 use crate::{
     parser::{parse_location::Parsed, ruulang_ast::Grant, schema_ast::Relationship},
     utils::trie::Trie,
 };
 
+/// A relationship or grant declared again by a part of an entity, along
+/// with the declaration that was kept.
+#[derive(Debug, Clone)]
+pub enum Conflict {
+    Relationship {
+        duplicate: Box<Parsed<Relationship>>,
+        original: Box<Parsed<Relationship>>,
+    },
+    Grant {
+        duplicate: Box<Parsed<Grant>>,
+        original: Box<Parsed<Grant>>,
+    },
+}
+
+impl Conflict {
+    /// The file declaring the duplicate.
+    pub fn file_name(&self) -> Option<&PathBuf> {
+        match self {
+            Conflict::Relationship { duplicate, .. } => duplicate.file_name.as_ref(),
+            Conflict::Grant { duplicate, .. } => duplicate.file_name.as_ref(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TcEntity {
     pub name: String,
 
     relationships: HashMap<String, Parsed<Relationship>>,
     grants: Trie<String, Parsed<Grant>>,
+
+    /// Whether any grant declares what it implies or is abstract
+    lattice: bool,
+
+    /// Declarations dropped when the parts of the entity were merged
+    conflicts: Vec<Conflict>,
 }
 
 impl TcEntity {
@@ -19,28 +52,41 @@ impl TcEntity {
             name,
             relationships: HashMap::new(),
             grants: Trie::new(),
+            lattice: false,
+            conflicts: vec![],
         };
     }
 
-    pub fn add_relationship(&mut self, rel: Parsed<Relationship>) -> bool {
-        if self
-            .relationships
-            .contains_key(&rel.data.relationship_name.data.value)
-        {
-            return false;
+    /// Adds a relationship, unless one by the same name was added before,
+    /// in which case the first is kept and the conflict recorded.
+    pub fn add_relationship(&mut self, rel: Parsed<Relationship>) {
+        let name = &rel.data.relationship_name.data.value;
+
+        if let Some(original) = self.relationships.get(name) {
+            self.conflicts.push(Conflict::Relationship {
+                original: Box::new(original.clone()),
+                duplicate: Box::new(rel),
+            });
+            return;
         }
 
         let rels = &mut self.relationships;
-        rels.insert(rel.data.relationship_name.data.value.clone(), rel);
-
-        true
+        rels.insert(name.clone(), rel);
     }
 
+    /// Adds a grant, unless it was added before, in which case the first is
+    /// kept and the conflict recorded.
     pub fn add_grant(&mut self, grant: Parsed<Grant>) {
-        if self.grants.contains(&grant.data) {
+        if let Some(original) = self.grants.get(&grant.data) {
+            self.conflicts.push(Conflict::Grant {
+                original: Box::new(original.clone()),
+                duplicate: Box::new(grant),
+            });
             return;
         }
 
+        self.lattice |= grant.data.is_abstract || !grant.data.implies.is_empty();
+
         let new_grant = grant.clone();
         self.grants.add(&grant.data, new_grant);
     }
@@ -49,7 +95,70 @@ impl TcEntity {
         self.relationships.get(rule)
     }
 
+    pub fn relationships(&self) -> impl Iterator<Item = &Parsed<Relationship>> {
+        self.relationships.values()
+    }
+
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Every grant declared on the entity, including dotted sub-grants.
+    pub fn grants(&self) -> Vec<&Parsed<Grant>> {
+        self.grants.values_under(&[])
+    }
+
+    /// Whether the entity declares how its grants imply each other. An
+    /// entity that doesn't keeps the implicit hierarchy, where a grant covers
+    /// every grant beneath it.
+    pub fn declares_lattice(&self) -> bool {
+        self.lattice
+    }
+
     pub fn allows_grant(&self, grant: &Vec<String>) -> bool {
-        self.grants.contains_prefix(grant)
+        match self.lattice {
+            true => self.grants.contains(grant),
+            false => self.grants.contains_prefix(grant),
+        }
+    }
+
+    /// Every grant conferred along with `grant`, including itself, following
+    /// the `implies` clauses and abstract declarations of this entity.
+    pub fn implied_grants(&self, grant: &[String]) -> Vec<Vec<String>> {
+        let mut implied = vec![];
+        let mut seen = HashSet::new();
+        let mut pending = vec![grant.to_vec()];
+
+        while let Some(current) = pending.pop() {
+            if !seen.insert(current.clone()) {
+                continue;
+            }
+
+            if let Some(decl) = self.grants.get(&current) {
+                pending.extend(decl.data.implies.iter().map(|x| x.data.grant.clone()));
+
+                if decl.data.is_abstract {
+                    pending.extend(
+                        self.grants
+                            .values_under(&current)
+                            .into_iter()
+                            .map(|x| x.data.grant.clone()),
+                    );
+                }
+            }
+
+            implied.push(current);
+        }
+
+        // Keep the grant itself first, and the rest in a stable order
+        implied[1..].sort();
+        implied
+    }
+
+    pub fn implies(&self, grant: &[String], other: &[String]) -> bool {
+        match self.lattice {
+            true => self.implied_grants(grant).iter().any(|x| x == other),
+            false => other.starts_with(grant),
+        }
     }
 }
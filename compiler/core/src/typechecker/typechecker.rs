@@ -1,23 +1,109 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
 
 use crate::{
     parser::{
-        parse_location::Parsed,
-        ruulang_ast::{Entrypoint, Fragment, Rule, RuuLangFile},
-        schema_ast::Entity,
+        parse_location::{DescendableChildren, Identifier, Parsed},
+        ruulang_ast::{
+            Attribute, AttributeParameter, Condition, Entrypoint, Fragment, Grant, Import, Rule,
+            RuuLangFile,
+        },
+        schema_ast::{ContextType, ContextVariable, Entity, Relationship},
     },
     utils::{
-        error::{Result, RuuLangError, TypecheckError},
+        error::{Result, RuuLangError, TypecheckError, TypecheckErrorKind},
+        suggestions::closest_match,
         with_origin::WithOrigin,
     },
 };
 
-use super::tc_ast::TcEntity;
+use super::{
+    modules::{split_reference, ModuleIndex, ResolveError},
+    recursion::Recursion,
+    tc_ast::{Conflict, TcEntity},
+    usage::Usage,
+};
+
+/// What universal rules grant on entities that don't declare it.
+fn default_universal_grants() -> &'static [Parsed<Grant>] {
+    static GRANTS: OnceLock<Vec<Parsed<Grant>>> = OnceLock::new();
+
+    GRANTS.get_or_init(|| {
+        ["read", "write"]
+            .into_iter()
+            .map(|grant| Parsed::new(Grant::new(vec![grant.to_string()]), None, None, None))
+            .collect()
+    })
+}
+
+/// A fragment along with the file and module it was declared in, and the
+/// resolved name of the entity it applies to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FragmentDecl<'a> {
+    pub origin: &'a PathBuf,
+    pub module: &'a str,
+    pub fragment: &'a Parsed<Fragment>,
+}
+
+impl FragmentDecl<'_> {
+    pub fn id(&self) -> FragmentId {
+        fragment_id(self.module, self.fragment)
+    }
+}
+
+/// Identifies a fragment by the module declaring it, its name and the entity
+/// reference it is declared for. Unlike the fragment's address, this stays
+/// the same while other files are edited.
+pub(crate) type FragmentId = (String, String, String);
+
+pub(crate) fn fragment_id(module: &str, fragment: &Parsed<Fragment>) -> FragmentId {
+    (
+        module.to_string(),
+        fragment.data.name.data.value.clone(),
+        fragment.data.for_entity.data.value.clone(),
+    )
+}
+
+/// The entities of the workspace, keyed by resolved name.
+pub(crate) type EntityMap = HashMap<String, Box<TcEntity>>;
+
+/// What the typechecker works out by walking the whole workspace. None of it
+/// borrows from the files, so it can be kept between edits that don't change
+/// what it was worked out from.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Analysis {
+    pub entities: Arc<EntityMap>,
+    usage: Arc<Usage>,
+    recursion: Arc<Recursion>,
+}
 
 #[derive(Debug, Clone)]
 pub struct Typechecker<'a> {
-    entities: HashMap<String, Box<TcEntity>>,
-    fragments: HashMap<(String, String), &'a Parsed<Fragment>>,
+    modules: ModuleIndex<'a>,
+    entities: Arc<EntityMap>,
+
+    /// Keyed by fragment name and resolved entity name
+    fragments: HashMap<(String, String), Vec<FragmentDecl<'a>>>,
+
+    /// Every part of every entity, as declared
+    declared: &'a [WithOrigin<Parsed<Entity>>],
+
+    /// Every entrypoint along with the module it was declared in
+    entrypoints: Vec<(&'a str, &'a Parsed<Entrypoint>)>,
+
+    /// The declared universal grants of each entity, by resolved name, along
+    /// with the file declaring them
+    universal: HashMap<String, Vec<(&'a PathBuf, &'a Parsed<Grant>)>>,
+
+    /// The context variables rules can be conditioned on, by name. Each is
+    /// declared once for the whole workspace
+    context: HashMap<String, &'a Parsed<ContextVariable>>,
+
+    usage: Arc<Usage>,
+    recursion: Arc<Recursion>,
 }
 
 impl<'a> Typechecker<'a> {
@@ -25,95 +111,287 @@ impl<'a> Typechecker<'a> {
         entities: &'a Vec<WithOrigin<Parsed<Entity>>>,
         schemas: &'a Vec<WithOrigin<Result<RuuLangFile>>>,
     ) -> Self {
-        let entity_map = Typechecker::parse_entities(entities, schemas);
-        let fragments = Typechecker::parse_fragments(entities, schemas);
+        let entity_map = Typechecker::entity_map(entities, schemas);
 
-        Self {
+        Typechecker::with_entity_map(entities, schemas, entity_map)
+    }
+
+    /// Builds the entities of the workspace, keyed by resolved name. The map
+    /// only depends on the entities and on the imports of the files that
+    /// declare them, so it can be reused while neither changes.
+    pub(crate) fn entity_map(
+        entities: &'a Vec<WithOrigin<Parsed<Entity>>>,
+        schemas: &'a Vec<WithOrigin<Result<RuuLangFile>>>,
+    ) -> Arc<EntityMap> {
+        let modules = ModuleIndex::new(entities, schemas);
+
+        Arc::new(Typechecker::parse_entities(&modules, entities))
+    }
+
+    /// Like `new`, but with an entity map built by `entity_map` for the same
+    /// entities.
+    pub(crate) fn with_entity_map(
+        entities: &'a Vec<WithOrigin<Parsed<Entity>>>,
+        schemas: &'a Vec<WithOrigin<Result<RuuLangFile>>>,
+        entity_map: Arc<EntityMap>,
+    ) -> Self {
+        let analysis = Analysis {
             entities: entity_map,
+            ..Analysis::default()
+        };
+
+        let mut typechecker = Typechecker::with_analysis(entities, schemas, &analysis);
+        typechecker.usage = Arc::new(Usage::new(&typechecker));
+        typechecker.recursion = Arc::new(Recursion::new(&typechecker));
+
+        typechecker
+    }
+
+    /// Like `new`, but reusing the analysis of a typechecker for the same
+    /// entities and policies, as returned by `analysis`.
+    pub(crate) fn with_analysis(
+        entities: &'a Vec<WithOrigin<Parsed<Entity>>>,
+        schemas: &'a Vec<WithOrigin<Result<RuuLangFile>>>,
+        analysis: &Analysis,
+    ) -> Self {
+        let modules = ModuleIndex::new(entities, schemas);
+        let fragments = Typechecker::parse_fragments(&modules, schemas);
+        let universal = Typechecker::parse_universal(&modules, entities);
+        let context = Typechecker::parse_context(schemas);
+        let entrypoints = schemas
+            .iter()
+            .filter_map(|schema| schema.data.as_ref().ok())
+            .flat_map(|file| {
+                file.entrypoints
+                    .iter()
+                    .map(|entrypoint| (file.module.as_str(), entrypoint))
+            })
+            .collect();
+
+        Self {
+            modules,
+            entities: analysis.entities.clone(),
             fragments,
+            declared: entities,
+            entrypoints,
+            universal,
+            context,
+            usage: analysis.usage.clone(),
+            recursion: analysis.recursion.clone(),
+        }
+    }
+
+    pub(crate) fn analysis(&self) -> Analysis {
+        Analysis {
+            entities: self.entities.clone(),
+            usage: self.usage.clone(),
+            recursion: self.recursion.clone(),
         }
     }
 
     pub fn validate_file(&self, file: &RuuLangFile) -> Vec<RuuLangError> {
         let mut violations = vec![];
+        let module = file.module.as_str();
+
+        let mut aliases = HashMap::<&str, &Parsed<Import>>::new();
+        for import in &file.imports {
+            let module_name = &import.data.module.data.value;
+            if !self.modules.contains_module(module_name) {
+                let missing_module_error = TypecheckError::new(
+                    &import.data.module,
+                    TypecheckErrorKind::UnknownModule {
+                        module: module_name.clone(),
+                    },
+                );
+
+                violations.push(missing_module_error.into());
+            }
+
+            if let Some(original) = aliases.insert(import.data.name(), import) {
+                let duplicate_error = TypecheckError::new(
+                    import,
+                    TypecheckErrorKind::DuplicateImport {
+                        alias: import.data.name().to_string(),
+                    },
+                )
+                .with_related(
+                    original,
+                    format!("{} is first imported here", import.data.name()),
+                );
+
+                violations.push(duplicate_error.into());
+            }
+        }
+
+        let mut duplicate_errors = self.validate_duplicates(module, file);
+        violations.append(&mut duplicate_errors);
+
+        let mut unused_errors = self.validate_usage(module, file);
+        violations.append(&mut unused_errors);
+
+        let mut recursion_errors = self.validate_recursion(module, file);
+        violations.append(&mut recursion_errors);
+
+        for variable in &file.context {
+            let Some(original) = self.context.get(&variable.data.name.data.value) else {
+                continue;
+            };
+
+            if std::ptr::eq(*original, variable) {
+                continue;
+            }
+
+            let mut error = TypecheckError::new(
+                &variable.data.name,
+                TypecheckErrorKind::DuplicateContextVariable {
+                    variable: variable.data.name.data.value.clone(),
+                },
+            );
+
+            // Related locations are always in the same file
+            if file.context.iter().any(|x| std::ptr::eq(x, *original)) {
+                error = error.with_related(
+                    &original.data.name,
+                    format!("{} is first declared here", original.data.name.data),
+                );
+            }
+
+            violations.push(error.into());
+        }
+
+        for entity in &file.entities {
+            // An entity may be declared in parts, so check against all of them
+            let resolved = self
+                .resolve_entity(module, &entity.data.name.data.value)
+                .ok();
+
+            for grant in entity.data.universal.iter().flatten() {
+                if resolved.map_or(true, |x| x.allows_grant(&grant.data.grant)) {
+                    continue;
+                }
+
+                let error = TypecheckError::new(
+                    grant,
+                    TypecheckErrorKind::UndeclaredUniversalGrant {
+                        entity: entity.data.name.data.value.clone(),
+                        grant: grant.data.to_string(),
+                    },
+                );
+                violations.push(error.into());
+            }
+
+            for grant in &entity.data.grants {
+                for implied in &grant.data.implies {
+                    if resolved.map_or(true, |x| x.allows_grant(&implied.data.grant)) {
+                        continue;
+                    }
+
+                    let error = TypecheckError::new(
+                        implied,
+                        TypecheckErrorKind::UndeclaredImpliedGrant {
+                            entity: entity.data.name.data.value.clone(),
+                            grant: grant.data.to_string(),
+                            implied: implied.data.to_string(),
+                        },
+                    );
+                    violations.push(error.into());
+                }
+            }
+
+            for relationship in &entity.data.relationships {
+                let target = &relationship.data.entity_name;
+
+                if let Err(error) = self.modules.resolve_entity(module, &target.data.value) {
+                    let missing_entity = TypecheckErrorKind::UnknownEntity {
+                        entity: target.data.value.clone(),
+                    };
+                    violations.push(self.resolve_error(target, error, missing_entity));
+                }
+            }
+        }
 
         for fragment in &file.fragments {
-            let mut downstream_errors = self.validate_fragment(&fragment);
+            let mut downstream_errors = self.validate_fragment(module, fragment);
             violations.append(&mut downstream_errors);
         }
 
         for entrypoint in &file.entrypoints {
-            let mut downstream_errors = self.validate_entrypoint(&entrypoint);
+            let mut downstream_errors = self.validate_entrypoint(module, entrypoint);
             violations.append(&mut downstream_errors);
         }
 
         violations
     }
 
-    fn validate_entrypoint(&self, entrypoint: &Parsed<Entrypoint>) -> Vec<RuuLangError> {
+    fn validate_entrypoint(
+        &self,
+        module: &str,
+        entrypoint: &Parsed<Entrypoint>,
+    ) -> Vec<RuuLangError> {
         let mut violations = vec![];
 
-        let starting_entity = match self.entities.get(&entrypoint.entrypoint.data.value) {
-            None => {
-                let missing_entity_name = &entrypoint.data.entrypoint;
-                let missing_entity_error = entrypoint.as_with_data(format!(
-                    "Unable to find entity name: {}",
-                    missing_entity_name
-                ));
-
-                violations.push(RuuLangError::TypecheckError(TypecheckError::GeneralError(
-                    missing_entity_error,
-                )));
+        let starting_entity = match self.resolve_entity(module, &entrypoint.entrypoint) {
+            Err(error) => {
+                let missing_entity = TypecheckErrorKind::UnknownEntity {
+                    entity: entrypoint.data.entrypoint.data.value.clone(),
+                };
+                violations.push(self.resolve_error(entrypoint, error, missing_entity));
                 return violations;
             }
 
-            Some(c) => c,
+            Ok(c) => c,
         };
 
         for rule in &entrypoint.data.rules {
-            let mut downstream_errors = self.validate_rule(starting_entity, rule);
+            let mut downstream_errors = self.validate_rule(module, starting_entity, rule);
             violations.append(&mut downstream_errors);
         }
 
         violations
     }
 
-    fn validate_fragment(&self, fragment: &Parsed<Fragment>) -> Vec<RuuLangError> {
+    fn validate_fragment(&self, module: &str, fragment: &Parsed<Fragment>) -> Vec<RuuLangError> {
         let mut violations = vec![];
 
-        let starting_entity = match self.entities.get(&fragment.for_entity.data.value) {
-            None => {
-                let missing_entity_name = &fragment.data.for_entity;
-                let missing_entity_error = fragment.as_with_data(format!(
-                    "Unable to find entity name: {}",
-                    missing_entity_name
-                ));
-
-                violations.push(RuuLangError::TypecheckError(TypecheckError::GeneralError(
-                    missing_entity_error,
-                )));
+        let starting_entity = match self.resolve_entity(module, &fragment.for_entity) {
+            Err(error) => {
+                let missing_entity = TypecheckErrorKind::UnknownEntity {
+                    entity: fragment.data.for_entity.data.value.clone(),
+                };
+                violations.push(self.resolve_error(fragment, error, missing_entity));
                 return violations;
             }
 
-            Some(c) => c,
+            Ok(c) => c,
         };
 
+        let mut duplicate_errors = duplicate_grants(&fragment.data.grants);
+        violations.append(&mut duplicate_errors);
+
+        let mut redundant_errors = redundant_grants(starting_entity, &fragment.data.grants, &[]);
+        violations.append(&mut redundant_errors);
+
+        let conferred = fragment.data.grants.iter().collect::<Vec<_>>();
+        let mut denial_errors =
+            ineffective_denials(starting_entity, &fragment.data.grants, &conferred);
+        violations.append(&mut denial_errors);
+
         for grant in &fragment.data.grants {
             if !starting_entity.allows_grant(&grant) {
-                let grant_str = grant.data.join(".");
-                let grant_error = grant.as_with_data(format!(
-                    "Entity {} does not allow grant: {}",
-                    starting_entity.name, grant_str
-                ));
+                let grant_error = TypecheckError::new(
+                    grant,
+                    TypecheckErrorKind::InvalidGrant {
+                        entity: starting_entity.name.clone(),
+                        grant: grant.data.join("."),
+                    },
+                );
 
-                violations.push(RuuLangError::TypecheckError(TypecheckError::GeneralError(
-                    grant_error,
-                )));
+                violations.push(grant_error.into());
             }
         }
 
         for rule in &fragment.data.rules {
-            let mut downstream_errors = self.validate_rule(starting_entity, rule);
+            let mut downstream_errors = self.validate_rule(module, starting_entity, rule);
             violations.append(&mut downstream_errors);
         }
 
@@ -122,28 +400,37 @@ impl<'a> Typechecker<'a> {
 
     fn validate_rule(
         &self,
+        module: &str,
         starting_entity: &TcEntity,
         current_rule: &Parsed<Rule>,
     ) -> Vec<RuuLangError> {
         let mut violations = vec![];
 
+        for condition in &current_rule.data.conditions {
+            if let Some(error) = self.validate_condition(condition) {
+                violations.push(error);
+            }
+        }
+
         let current_rel = match starting_entity.get_rule(&current_rule.relationship) {
             None => {
+                // A `*` rule confers its grants on everything below. The rule
+                // around it already covers the entity it starts from
                 if &current_rule.data.relationship.data.value == "*" {
-                    return violations;
+                    let mut reachable = self.reachable_entities(starting_entity);
+                    reachable.retain(|x| x.name != starting_entity.name);
+                    return self.validate_universal(current_rule, reachable);
                 }
 
-                let missing_relationship_name = &current_rule.relationship;
-                let bad_entity_name = &starting_entity.name;
-
-                let missing_rule_error = current_rule.as_with_data(format!(
-                    "Relationship {} not found for entity {}",
-                    missing_relationship_name, bad_entity_name
-                ));
+                let missing_rule_error = TypecheckError::new(
+                    current_rule,
+                    TypecheckErrorKind::UnknownRelationship {
+                        entity: starting_entity.name.clone(),
+                        relationship: current_rule.data.relationship.data.value.clone(),
+                    },
+                );
 
-                violations.push(RuuLangError::TypecheckError(TypecheckError::GeneralError(
-                    missing_rule_error,
-                )));
+                violations.push(missing_rule_error.into());
 
                 return violations;
             }
@@ -151,121 +438,927 @@ impl<'a> Typechecker<'a> {
             Some(c) => c,
         };
 
+        for attribute in &current_rule.data.attributes {
+            let mut downstream_errors =
+                self.validate_attribute(starting_entity, current_rel, attribute);
+            violations.append(&mut downstream_errors);
+        }
+
         let current_entity = match self.entities.get(&current_rel.entity_name.data.value) {
             None => {
-                let missing_entity_name = current_rel.data.entity_name.clone();
-                let missing_entity_error = current_rel.as_with_data(format!(
-                    "Unable to find entity name: {}",
-                    missing_entity_name
-                ));
+                let missing_entity_error = TypecheckError::new(
+                    current_rel,
+                    TypecheckErrorKind::UnknownEntity {
+                        entity: current_rel.data.entity_name.data.value.clone(),
+                    },
+                );
 
-                violations.push(RuuLangError::TypecheckError(TypecheckError::GeneralError(
-                    missing_entity_error,
-                )));
+                violations.push(missing_entity_error.into());
                 return violations;
             }
 
             Some(c) => c,
         };
 
+        let mut duplicate_errors = duplicate_grants(&current_rule.data.grants);
+        violations.append(&mut duplicate_errors);
+
+        if current_rule.data.universal {
+            let mut universal_errors = self.validate_universal(current_rule, vec![current_entity]);
+            violations.append(&mut universal_errors);
+        }
+
         for grant in &current_rule.data.grants {
             if !current_entity.allows_grant(&grant) {
-                let grant_name = grant.data.clone().join(".");
-
-                let error = grant.as_with_data(format!("Invalid grant {}", grant_name));
-                violations.push(RuuLangError::TypecheckError(TypecheckError::GeneralError(
-                    error,
-                )));
+                let error = TypecheckError::new(
+                    grant,
+                    TypecheckErrorKind::InvalidGrant {
+                        entity: current_entity.name.clone(),
+                        grant: grant.data.join("."),
+                    },
+                );
+                violations.push(error.into());
             }
         }
 
+        let mut included = vec![];
         for included_fragment in &current_rule.data.include_fragments {
-            let fragment_key = (
-                included_fragment.data.value.clone(),
-                current_entity.name.clone(),
-            );
+            match self.resolve_fragment(module, &included_fragment.data.value, &current_entity.name)
+            {
+                Err(error) => {
+                    let missing_fragment = TypecheckErrorKind::UnknownFragment {
+                        fragment: included_fragment.data.value.clone(),
+                        entity: current_entity.name.clone(),
+                    };
+                    violations.push(self.resolve_error(included_fragment, error, missing_fragment));
+                }
+                Ok(c) => included.push(c),
+            };
+        }
 
-            match self.fragments.get(&fragment_key) {
-                None => {
-                    let missing_fragment_error = included_fragment.as_with_data(format!(
-                        "Unable to find fragment name: {} for entity {}",
-                        &fragment_key.0, &fragment_key.1
-                    ));
+        let mut redundant_errors =
+            redundant_grants(current_entity, &current_rule.data.grants, &included);
+        violations.append(&mut redundant_errors);
 
-                    violations.push(RuuLangError::TypecheckError(TypecheckError::GeneralError(
-                        missing_fragment_error,
-                    )));
+        // A negated grant only withholds grants conferred by the same rule
+        let conferred = included
+            .iter()
+            .flat_map(|fragment| &fragment.fragment.data.grants)
+            .chain(&current_rule.data.grants)
+            .collect::<Vec<_>>();
+
+        let mut denial_errors =
+            ineffective_denials(current_entity, &current_rule.data.grants, &conferred);
+        violations.append(&mut denial_errors);
+
+        for rule in &current_rule.data.rules {
+            let mut downstream_errors = self.validate_rule(module, current_entity, rule);
+            violations.append(&mut downstream_errors);
+        }
+
+        violations
+    }
+
+    /// Checks for relationships, grants and fragments that are declared more
+    /// than once in the workspace. The parts of an entity in a namespace are
+    /// merged, so they may not redeclare each other's relationships or
+    /// grants. Each duplicate is reported in the file declaring it, along
+    /// with the declaration it conflicts with.
+    fn validate_duplicates(&self, module: &str, file: &RuuLangFile) -> Vec<RuuLangError> {
+        let mut violations = vec![];
+        let origin = self.modules.origin_of(module);
+
+        let mut entity_names = file
+            .entities
+            .iter()
+            .map(|x| self.modules.entity_name(module, &x.data.name.data.value))
+            .collect::<Vec<_>>();
+        entity_names.sort();
+        entity_names.dedup();
+
+        for entity in entity_names.iter().filter_map(|x| self.entity(x)) {
+            let conflicts = entity.conflicts().iter();
+
+            for conflict in conflicts.filter(|x| x.file_name() == origin) {
+                violations.push(match conflict {
+                    Conflict::Relationship {
+                        duplicate,
+                        original,
+                    } => duplicate_error(
+                        duplicate,
+                        TypecheckErrorKind::DuplicateRelationship {
+                            relationship: duplicate.data.relationship_name.data.value.clone(),
+                            target: duplicate.data.entity_name.data.value.clone(),
+                            original_target: original.data.entity_name.data.value.clone(),
+                        },
+                        original,
+                        format!(
+                            "{} is first declared here",
+                            original.data.relationship_name.data
+                        ),
+                    ),
+                    Conflict::Grant {
+                        duplicate,
+                        original,
+                    } => duplicate_error(
+                        duplicate,
+                        TypecheckErrorKind::DuplicateGrant {
+                            grant: duplicate.data.to_string(),
+                            entity: Some(entity.name.clone()),
+                        },
+                        original,
+                        format!("{} is first declared here", original.data),
+                    ),
+                });
+            }
+        }
+
+        let namespace = self.modules.namespace(module);
+        for fragment in &file.fragments {
+            let Some((entity, declared)) = self.fragment_decls(module, fragment) else {
+                continue;
+            };
+
+            // References resolve to the first declaration in the namespace
+            let Some(original) = declared
+                .iter()
+                .find(|x| self.modules.namespace(x.module) == namespace)
+            else {
+                continue;
+            };
+
+            if !std::ptr::eq(original.fragment, fragment) {
+                let (original_name, _) = original
+                    .fragment
+                    .data
+                    .name
+                    .clone()
+                    .into_with_filename(original.origin.clone());
+
+                violations.push(duplicate_error(
+                    &fragment.data.name,
+                    TypecheckErrorKind::DuplicateFragment {
+                        fragment: fragment.data.name.data.value.clone(),
+                        entity,
+                    },
+                    &original_name,
+                    format!("{} is first declared here", original_name.data),
+                ));
+            }
+        }
+
+        violations.append(&mut self.validate_shadowing(module, file));
+
+        violations
+    }
+
+    /// Warns about entities and fragments that hide a declaration of the same
+    /// name in an imported module, which unqualified names then can't refer
+    /// to.
+    fn validate_shadowing(&self, module: &str, file: &RuuLangFile) -> Vec<RuuLangError> {
+        let mut violations = vec![];
+
+        for import in &file.imports {
+            let imported = import.data.module.data.value.as_str();
+            let namespace = self.modules.namespace(imported);
+
+            if namespace == self.modules.namespace(module)
+                || !self.modules.contains_module(imported)
+            {
+                continue;
+            }
+
+            let shadowed_error = |name: &Parsed<Identifier>, hidden: Parsed<Identifier>| {
+                TypecheckError::new(
+                    name,
+                    TypecheckErrorKind::ShadowedImport {
+                        name: name.data.value.clone(),
+                        module: imported.to_string(),
+                    },
+                )
+                .with_related(&hidden, format!("{} is declared here", hidden.data))
+                .into()
+            };
+
+            for entity in &file.entities {
+                let name = &entity.data.name;
+                let hidden = self.declared.iter().find(|x| {
+                    x.data.data.name.data.value == name.data.value
+                        && self.modules.namespace(self.modules.module_of(&x.origin)) == namespace
+                });
+
+                if let Some(hidden) = hidden {
+                    let (hidden_name, _) = hidden
+                        .data
+                        .data
+                        .name
+                        .clone()
+                        .into_with_filename(hidden.origin.clone());
+                    violations.push(shadowed_error(name, hidden_name));
+                }
+            }
+
+            for fragment in &file.fragments {
+                let Some((_, declared)) = self.fragment_decls(module, fragment) else {
                     continue;
+                };
+
+                let hidden = declared
+                    .iter()
+                    .find(|x| self.modules.namespace(x.module) == namespace);
+
+                if let Some(hidden) = hidden {
+                    let (hidden_name, _) = hidden
+                        .fragment
+                        .data
+                        .name
+                        .clone()
+                        .into_with_filename(hidden.origin.clone());
+                    violations.push(shadowed_error(&fragment.data.name, hidden_name));
                 }
-                Some(c) => c,
+            }
+        }
+
+        violations
+    }
+
+    /// The resolved name of the entity a fragment declared in `module` is
+    /// for, along with every fragment declared by the same name for it.
+    fn fragment_decls(
+        &self,
+        module: &str,
+        fragment: &Parsed<Fragment>,
+    ) -> Option<(String, &[FragmentDecl<'a>])> {
+        let entity = self
+            .resolve_entity(module, &fragment.data.for_entity)
+            .ok()?;
+        let key = (fragment.data.name.data.value.clone(), entity.name.clone());
+
+        Some((
+            entity.name.clone(),
+            self.fragments.get(&key).map_or(&[][..], |x| x.as_slice()),
+        ))
+    }
+
+    /// Warns about fragments, entities and relationships in a file that no
+    /// policy in the workspace uses.
+    fn validate_usage(&self, module: &str, file: &RuuLangFile) -> Vec<RuuLangError> {
+        let mut violations = vec![];
+
+        for fragment in &file.fragments {
+            let Ok(entity) = self.resolve_entity(module, &fragment.data.for_entity) else {
+                continue;
             };
+
+            if !self.usage.uses_fragment(module, fragment) {
+                let unused_error = TypecheckError::new(
+                    &fragment.data.name,
+                    TypecheckErrorKind::UnusedFragment {
+                        fragment: fragment.data.name.data.value.clone(),
+                        entity: entity.name.clone(),
+                    },
+                );
+
+                violations.push(unused_error.into());
+            }
         }
 
-        for rule in &current_rule.data.rules {
-            let mut downstream_errors = self.validate_rule(&current_entity, rule);
-            violations.append(&mut downstream_errors);
+        for entity in &file.entities {
+            let entity_name = self
+                .modules
+                .entity_name(module, &entity.data.name.data.value);
+
+            if !self.usage.reaches_entity(&entity_name) {
+                let unreachable_error = TypecheckError::new(
+                    &entity.data.name,
+                    TypecheckErrorKind::UnreachableEntity {
+                        entity: entity_name,
+                    },
+                );
+
+                violations.push(unreachable_error.into());
+                continue;
+            }
+
+            for relationship in &entity.data.relationships {
+                let relationship_name = &relationship.data.relationship_name.data.value;
+                if self.usage.traverses(&entity_name, relationship_name) {
+                    continue;
+                }
+
+                let dead_error = TypecheckError::new(
+                    relationship,
+                    TypecheckErrorKind::DeadRelationship {
+                        entity: entity_name.clone(),
+                        relationship: relationship_name.clone(),
+                    },
+                );
+
+                violations.push(dead_error.into());
+            }
         }
 
         violations
     }
 
+    /// Warns about fragments that include themselves, as nothing bounds how
+    /// deep their policy applies.
+    fn validate_recursion(&self, module: &str, file: &RuuLangFile) -> Vec<RuuLangError> {
+        let mut violations = vec![];
+
+        for fragment in &file.fragments {
+            let Some(cycle) = self.recursion.cycle(module, fragment) else {
+                continue;
+            };
+
+            let Ok(entity) = self.resolve_entity(module, &fragment.data.for_entity) else {
+                continue;
+            };
+
+            let recursion_error = TypecheckError::new(
+                &fragment.data.name,
+                TypecheckErrorKind::UnboundedRecursion {
+                    fragment: fragment.data.name.data.value.clone(),
+                    entity: entity.name.clone(),
+                    cycle: cycle.steps.join(" -> "),
+                },
+            )
+            .with_related(
+                &cycle.include,
+                format!("{} starts recursing here", fragment.data.name.data.value),
+            );
+
+            violations.push(recursion_error.into());
+        }
+
+        violations
+    }
+
+    /// Reports the grants a universal rule confers on `entities` that they
+    /// don't declare. Declared universal grants are checked with the entity.
+    fn validate_universal(
+        &self,
+        rule: &Parsed<Rule>,
+        entities: Vec<&TcEntity>,
+    ) -> Vec<RuuLangError> {
+        let mut violations = vec![];
+
+        for entity in entities {
+            if self.universal.contains_key(&entity.name) {
+                continue;
+            }
+
+            for grant in default_universal_grants().iter() {
+                if entity.allows_grant(&grant.data.grant) {
+                    continue;
+                }
+
+                let error = TypecheckError::new(
+                    rule,
+                    TypecheckErrorKind::UndeclaredUniversalGrant {
+                        entity: entity.name.clone(),
+                        grant: grant.data.to_string(),
+                    },
+                );
+                violations.push(error.into());
+            }
+        }
+
+        violations
+    }
+
+    /// Every entity reachable from `entity` through at least one relationship.
+    fn reachable_entities(&self, entity: &TcEntity) -> Vec<&TcEntity> {
+        let mut reachable = vec![];
+        let mut seen = HashSet::new();
+        let mut pending = vec![entity];
+
+        while let Some(current) = pending.pop() {
+            for relationship in current.relationships() {
+                let Some(target) = self.entity(&relationship.data.entity_name.data.value) else {
+                    continue;
+                };
+
+                if seen.insert(target.name.as_str()) {
+                    reachable.push(target);
+                    pending.push(target);
+                }
+            }
+        }
+
+        reachable
+    }
+
+    /// The grants universal rules confer on the entity with the resolved name
+    /// `entity`, along with the file declaring each one, if any.
+    pub(crate) fn universal_grants(
+        &self,
+        entity: &str,
+    ) -> Vec<(Option<&'a PathBuf>, &'a Parsed<Grant>)> {
+        match self.universal.get(entity) {
+            Some(grants) => grants
+                .iter()
+                .map(|(origin, grant)| (Some(*origin), *grant))
+                .collect(),
+            None => default_universal_grants()
+                .iter()
+                .map(|grant| (None, grant))
+                .collect(),
+        }
+    }
+
+    /// Whether a fragment declared in `module` can include itself through
+    /// its rules.
+    pub(crate) fn is_recursive(&self, module: &str, fragment: &Parsed<Fragment>) -> bool {
+        self.recursion.is_recursive(module, fragment)
+    }
+
+    /// The grants negated by the rules traversing `relationship` of the
+    /// entity with the resolved name `entity`.
+    pub(crate) fn denied_grants(&self, entity: &str, relationship: &str) -> &[Vec<String>] {
+        self.usage.denials(entity, relationship)
+    }
+
+    /// Looks up an entity by its resolved name.
+    pub(crate) fn entity(&self, name: &str) -> Option<&TcEntity> {
+        self.entities.get(name).map(|entity| entity.as_ref())
+    }
+
+    pub(crate) fn modules(&self) -> &ModuleIndex<'a> {
+        &self.modules
+    }
+
+    /// Resolves an entity reference written in `module`.
+    pub(crate) fn resolve_entity(
+        &self,
+        module: &str,
+        reference: &str,
+    ) -> std::result::Result<&TcEntity, ResolveError> {
+        let name = self.modules.resolve_entity(module, reference)?;
+
+        self.entity(&name)
+            .ok_or_else(|| ResolveError::NotFound(reference.to_string()))
+    }
+
+    /// Resolves a fragment reference written in `module`, for the entity
+    /// with the resolved name `entity`.
+    pub(crate) fn resolve_fragment(
+        &self,
+        module: &str,
+        reference: &str,
+        entity: &str,
+    ) -> std::result::Result<&FragmentDecl<'a>, ResolveError> {
+        let (_, name) = split_reference(reference);
+        let declared = self
+            .fragments
+            .get(&(name.to_string(), entity.to_string()))
+            .map_or(&[][..], |x| x.as_slice());
+
+        let declared_in = declared.iter().map(|x| x.module).collect::<Vec<_>>();
+        let found = self.modules.resolve(module, reference, &declared_in)?;
+
+        Ok(declared.iter().find(|x| x.module == found).unwrap())
+    }
+
+    pub(crate) fn entrypoints(&self) -> &[(&'a str, &'a Parsed<Entrypoint>)] {
+        &self.entrypoints
+    }
+
+    /// Every fragment in the workspace along with the resolved name of the
+    /// entity it applies to.
+    pub(crate) fn fragments(&self) -> impl Iterator<Item = (&str, &FragmentDecl<'a>)> {
+        self.fragments
+            .iter()
+            .flat_map(|((_, entity), decls)| decls.iter().map(move |decl| (entity.as_str(), decl)))
+    }
+
+    fn resolve_error<T: for<'b> DescendableChildren<'b>>(
+        &self,
+        at: &Parsed<T>,
+        error: ResolveError,
+        not_found: TypecheckErrorKind,
+    ) -> RuuLangError {
+        let kind = match error {
+            ResolveError::UnknownModule(module) => TypecheckErrorKind::ModuleNotImported { module },
+            ResolveError::NotFound(_) => not_found,
+            ResolveError::Ambiguous(name, modules) => {
+                TypecheckErrorKind::AmbiguousName { name, modules }
+            }
+        };
+
+        TypecheckError::new(at, kind).into()
+    }
+
+    fn validate_attribute(
+        &self,
+        starting_entity: &TcEntity,
+        relationship: &Parsed<Relationship>,
+        attribute: &Parsed<Attribute>,
+    ) -> Vec<RuuLangError> {
+        let mut violations = vec![];
+
+        let attribute_name = &attribute.data.name.data.value;
+        let declared_names = relationship
+            .data
+            .attributes
+            .iter()
+            .map(|x| x.data.name.data.value.as_str());
+
+        let declared = match relationship
+            .data
+            .attributes
+            .iter()
+            .find(|x| &x.data.name.data.value == attribute_name)
+        {
+            None => {
+                let missing_attribute_error = TypecheckError::new(
+                    attribute,
+                    TypecheckErrorKind::UnknownAttribute {
+                        entity: starting_entity.name.clone(),
+                        relationship: relationship.data.relationship_name.data.value.clone(),
+                        attribute: attribute_name.clone(),
+                        suggestion: closest_match(attribute_name, declared_names)
+                            .map(|x| x.to_string()),
+                    },
+                );
+
+                violations.push(missing_attribute_error.into());
+                return violations;
+            }
+
+            Some(c) => c,
+        };
+
+        let Some(parameters) = &declared.data.parameters else {
+            return violations;
+        };
+
+        // Rules may name an attribute without arguments to match on its
+        // presence alone.
+        if attribute.arguments.is_empty() {
+            return violations;
+        }
+
+        if parameters.len() != attribute.arguments.len() {
+            let arity_error = TypecheckError::new(
+                attribute,
+                TypecheckErrorKind::AttributeArity {
+                    attribute: attribute_name.clone(),
+                    expected: parameters.len(),
+                    found: attribute.arguments.len(),
+                },
+            );
+
+            violations.push(arity_error.into());
+            return violations;
+        }
+
+        for (argument, parameter) in attribute.arguments.iter().zip(parameters) {
+            if parameter.accepts(argument) {
+                continue;
+            }
+
+            let suggestion = match parameter {
+                AttributeParameter::OneOf(values) => {
+                    closest_match(argument, values.iter().map(|x| x.as_str()))
+                        .map(|x| x.to_string())
+                }
+                _ => None,
+            };
+
+            let argument_error = TypecheckError::new(
+                attribute,
+                TypecheckErrorKind::InvalidAttributeArgument {
+                    attribute: attribute_name.clone(),
+                    argument: argument.to_string(),
+                    expected: parameter.to_string(),
+                    suggestion,
+                },
+            );
+
+            violations.push(argument_error.into());
+        }
+
+        violations
+    }
+
+    /// Checks that a condition names a declared context variable, and
+    /// compares it against a value of the variable's type.
+    fn validate_condition(&self, condition: &Parsed<Condition>) -> Option<RuuLangError> {
+        let name = &condition.data.variable.data.value;
+
+        let Some(declared) = self.context.get(name) else {
+            let missing_variable_error = TypecheckError::new(
+                &condition.data.variable,
+                TypecheckErrorKind::UnknownContextVariable {
+                    variable: name.clone(),
+                    suggestion: closest_match(name, self.context.keys().map(|x| x.as_str()))
+                        .map(|x| x.to_string()),
+                },
+            );
+
+            return Some(missing_variable_error.into());
+        };
+
+        let kind = &declared.data.kind;
+        if condition.data.operator.is_ordering() && kind != &ContextType::Int {
+            let operator_error = TypecheckError::new(
+                condition,
+                TypecheckErrorKind::InvalidConditionOperator {
+                    variable: name.clone(),
+                    operator: condition.data.operator.to_string(),
+                    kind: kind.to_string(),
+                },
+            );
+
+            return Some(operator_error.into());
+        }
+
+        if kind.accepts(&condition.data.value) {
+            return None;
+        }
+
+        let value = condition.data.value.to_string();
+        let suggestion = match kind {
+            ContextType::OneOf(values) => {
+                closest_match(&value, values.iter().map(|x| x.as_str())).map(|x| x.to_string())
+            }
+            _ => None,
+        };
+
+        let value_error = TypecheckError::new(
+            condition,
+            TypecheckErrorKind::InvalidConditionValue {
+                variable: name.clone(),
+                value,
+                expected: kind.to_string(),
+                suggestion,
+            },
+        );
+
+        Some(value_error.into())
+    }
+
     fn parse_entities(
+        modules: &ModuleIndex<'a>,
         entities: &'a Vec<WithOrigin<Parsed<Entity>>>,
-        schemas: &'a Vec<WithOrigin<Result<RuuLangFile>>>,
-    ) -> HashMap<String, Box<TcEntity>> {
-        let mut entity_map = HashMap::<String, Box<TcEntity>>::new();
-        let schemas = schemas.iter().filter_map(|x| match &x.data {
-            Ok(data) => {
-                let new_schema = x.as_with_data(data);
-                Some(new_schema)
-            }
-            Err(_) => None,
-        });
+    ) -> EntityMap {
+        let mut entity_map = EntityMap::new();
 
-        for origin in schemas {
-            entities.iter().for_each(|entity| {
-                let entity_name = &entity.data.data.name;
-                let key = entity_name.clone();
-                let name_for_insert = entity_name.clone();
-                let found_entity = entity_map
-                    .entry(key.data.value)
-                    .or_insert_with(|| Box::new(TcEntity::new(name_for_insert.data.value)));
-
-                entity.data.data.relationships.iter().for_each(|rel| {
-                    let (updated_rel, _) = rel.clone().into_with_filename(origin.origin.clone());
-                    found_entity.add_relationship(updated_rel);
-                });
+        // The first declaration of a relationship or grant is kept, and the
+        // others reported, so visit the files in a stable order
+        let mut entities = entities.iter().collect::<Vec<_>>();
+        entities.sort_by_key(|entity| &entity.origin);
 
-                entity.data.data.grants.iter().for_each(|grant| {
-                    let (updated_grant, _) =
-                        grant.clone().into_with_filename(origin.origin.clone());
-                    found_entity.add_grant(updated_grant)
-                });
+        for entity in entities {
+            let module = modules.module_of(&entity.origin);
+            let entity_name = modules.entity_name(module, &entity.data.data.name.data.value);
+            let found_entity = entity_map
+                .entry(entity_name.clone())
+                .or_insert_with(|| Box::new(TcEntity::new(entity_name)));
+
+            entity.data.data.relationships.iter().for_each(|rel| {
+                let (mut updated_rel, _) = rel.clone().into_with_filename(entity.origin.clone());
+
+                // Relationships point at the resolved name of their target
+                if let Ok(target) = modules.resolve_entity(module, &rel.data.entity_name.data.value)
+                {
+                    updated_rel.data.entity_name.data.value = target;
+                }
+
+                found_entity.add_relationship(updated_rel);
+            });
+
+            entity.data.data.grants.iter().for_each(|grant| {
+                let (updated_grant, _) = grant.clone().into_with_filename(entity.origin.clone());
+                found_entity.add_grant(updated_grant)
             });
         }
 
         entity_map
     }
 
+    fn parse_universal(
+        modules: &ModuleIndex<'a>,
+        entities: &'a Vec<WithOrigin<Parsed<Entity>>>,
+    ) -> HashMap<String, Vec<(&'a PathBuf, &'a Parsed<Grant>)>> {
+        let mut universal = HashMap::<String, Vec<_>>::new();
+
+        // Universal grants declared across several parts of an entity add up
+        for entity in entities {
+            let Some(grants) = &entity.data.data.universal else {
+                continue;
+            };
+
+            let module = modules.module_of(&entity.origin);
+            let entity_name = modules.entity_name(module, &entity.data.data.name.data.value);
+            universal
+                .entry(entity_name)
+                .or_default()
+                .extend(grants.iter().map(|grant| (&entity.origin, grant)));
+        }
+
+        universal
+    }
+
+    fn parse_context(
+        schemas: &'a [WithOrigin<Result<RuuLangFile>>],
+    ) -> HashMap<String, &'a Parsed<ContextVariable>> {
+        let mut context = HashMap::new();
+
+        // Later declarations of a variable are reported as duplicates, so
+        // visit the files in a stable order
+        let mut schemas = schemas.iter().collect::<Vec<_>>();
+        schemas.sort_by_key(|schema| &schema.origin);
+
+        for schema in schemas {
+            let Ok(file) = &schema.data else {
+                continue;
+            };
+
+            for variable in &file.context {
+                context
+                    .entry(variable.data.name.data.value.clone())
+                    .or_insert(variable);
+            }
+        }
+
+        context
+    }
+
     fn parse_fragments(
-        _entities: &'a Vec<WithOrigin<Parsed<Entity>>>,
+        modules: &ModuleIndex<'a>,
         schemas: &'a Vec<WithOrigin<Result<RuuLangFile>>>,
-    ) -> HashMap<(String, String), &'a Parsed<Fragment>> {
-        schemas
-            .into_iter()
-            .filter_map(|x| match &x.data {
-                Ok(data) => {
-                    let new_schema = x.as_with_data(data);
-                    Some(new_schema)
-                }
-                Err(_) => None,
-            })
-            .flat_map(|schema| &schema.data.fragments)
-            .map(|fragment| {
+    ) -> HashMap<(String, String), Vec<FragmentDecl<'a>>> {
+        let mut fragments = HashMap::<(String, String), Vec<FragmentDecl<'a>>>::new();
+
+        // The first declaration of a fragment is the one references resolve
+        // to, and the others are reported, so visit the files in a stable
+        // order
+        let mut schemas = schemas.iter().collect::<Vec<_>>();
+        schemas.sort_by_key(|schema| &schema.origin);
+
+        for schema in schemas {
+            let Ok(file) = &schema.data else {
+                continue;
+            };
+
+            for fragment in &file.fragments {
+                let Ok(entity_name) =
+                    modules.resolve_entity(&file.module, &fragment.data.for_entity.data.value)
+                else {
+                    continue;
+                };
+
                 let fragment_name = fragment.data.name.data.value.clone();
-                let entity_name = fragment.data.for_entity.data.value.clone();
-                ((fragment_name, entity_name), fragment)
+                fragments
+                    .entry((fragment_name, entity_name))
+                    .or_default()
+                    .push(FragmentDecl {
+                        origin: &schema.origin,
+                        module: file.module.as_str(),
+                        fragment,
+                    });
+            }
+        }
+
+        fragments
+    }
+}
+
+/// Reports the negated grants among `grants` that withhold nothing, as
+/// none of the grants in `conferred` implies them.
+fn ineffective_denials(
+    entity: &TcEntity,
+    grants: &[Parsed<Grant>],
+    conferred: &[&Parsed<Grant>],
+) -> Vec<RuuLangError> {
+    grants
+        .iter()
+        .filter(|grant| grant.data.negated)
+        .filter(|grant| {
+            !conferred.iter().any(|conferred| {
+                !conferred.data.negated && entity.implies(&conferred.data.grant, &grant.data.grant)
             })
-            .collect::<HashMap<_, _>>()
+        })
+        .map(|grant| {
+            TypecheckError::new(
+                grant,
+                TypecheckErrorKind::IneffectiveDenial {
+                    grant: grant.data.join("."),
+                },
+            )
+            .into()
+        })
+        .collect()
+}
+
+/// Reports grants that are repeated within the same rule or fragment.
+fn duplicate_grants(grants: &[Parsed<Grant>]) -> Vec<RuuLangError> {
+    let mut violations = vec![];
+
+    for (i, grant) in grants.iter().enumerate() {
+        if let Some(original) = grants[..i].iter().find(|x| x.data == grant.data) {
+            violations.push(duplicate_error(
+                grant,
+                TypecheckErrorKind::DuplicateGrant {
+                    grant: grant.data.to_string(),
+                    entity: None,
+                },
+                original,
+                format!("{} is first declared here", original.data),
+            ));
+        }
     }
+
+    violations
+}
+
+/// Reports grants that are implied by another grant conferred by the same
+/// rule, either directly or through one of the fragments it includes.
+fn redundant_grants(
+    entity: &TcEntity,
+    grants: &[Parsed<Grant>],
+    included: &[&FragmentDecl],
+) -> Vec<RuuLangError> {
+    let mut violations = vec![];
+
+    let declared = grants
+        .iter()
+        .filter(|x| !x.data.negated)
+        .collect::<Vec<_>>();
+    let inherited = included
+        .iter()
+        .flat_map(|decl| {
+            decl.fragment
+                .data
+                .grants
+                .iter()
+                .filter(|x| !x.data.negated)
+                .map(|grant| (grant, &decl.fragment.data.name.data.value))
+        })
+        .collect::<Vec<_>>();
+
+    for grant in &declared {
+        // A narrower grant may restore part of what a denial withholds
+        if grants
+            .iter()
+            .any(|x| x.data.negated && entity.implies(&x.data.grant, &grant.data.grant))
+        {
+            continue;
+        }
+
+        // Grants that imply each other are left alone, since neither one is
+        // broader than the other
+        let path = &grant.data.grant;
+        let covering = declared.iter().find(|x| {
+            x.data.grant != *path
+                && entity.implies(&x.data.grant, path)
+                && !entity.implies(path, &x.data.grant)
+        });
+
+        let error = if let Some(covering) = covering {
+            let kind = TypecheckErrorKind::RedundantGrant {
+                grant: grant.data.to_string(),
+                covered_by: covering.data.to_string(),
+                fragment: None,
+            };
+
+            TypecheckError::new(*grant, kind)
+                .with_related(*covering, format!("{} is granted here", covering.data))
+        } else if let Some((covering, fragment)) = inherited
+            .iter()
+            .find(|(x, _)| entity.implies(&x.data.grant, path))
+        {
+            let kind = TypecheckErrorKind::RedundantGrant {
+                grant: grant.data.to_string(),
+                covered_by: covering.data.to_string(),
+                fragment: Some(fragment.to_string()),
+            };
+
+            TypecheckError::new(*grant, kind)
+        } else {
+            continue;
+        };
+
+        let fix_message = format!("Remove the redundant grant {}", grant.data);
+        violations.push(error.with_removal(*grant, fix_message).into());
+    }
+
+    violations
+}
+
+fn duplicate_error<T, U>(
+    duplicate: &Parsed<T>,
+    kind: TypecheckErrorKind,
+    original: &Parsed<U>,
+    note: String,
+) -> RuuLangError
+where
+    T: for<'b> DescendableChildren<'b>,
+    U: for<'b> DescendableChildren<'b>,
+{
+    TypecheckError::new(duplicate, kind)
+        .with_related(original, note)
+        .into()
 }
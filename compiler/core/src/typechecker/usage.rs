@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::{
+    parse_location::Parsed,
+    ruulang_ast::{Fragment, Grant, Rule},
+};
+
+use super::{
+    tc_ast::TcEntity,
+    typechecker::{fragment_id, FragmentId, Typechecker},
+};
+
+/// What the policies in a workspace use, found by walking every entrypoint
+/// along with the fragments it includes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Usage {
+    /// Fragments included from an entrypoint or from a used fragment
+    fragments: HashSet<FragmentId>,
+
+    /// Resolved names of the entities a policy can reach
+    entities: HashSet<String>,
+
+    /// Relationships traversed by a policy, by resolved entity name and
+    /// relationship name
+    relationships: HashSet<(String, String)>,
+
+    /// Grants negated by the rules traversing each relationship, keyed like
+    /// `relationships`
+    denials: HashMap<(String, String), Vec<Vec<String>>>,
+}
+
+impl Usage {
+    pub fn new(typechecker: &Typechecker) -> Self {
+        let mut usage = Usage::default();
+
+        for (module, entrypoint) in typechecker.entrypoints() {
+            let Ok(entity) = typechecker.resolve_entity(module, &entrypoint.data.entrypoint) else {
+                continue;
+            };
+
+            usage.entities.insert(entity.name.clone());
+            for rule in &entrypoint.data.rules {
+                usage.visit_rule(typechecker, module, entity, rule);
+            }
+        }
+
+        usage
+    }
+
+    /// Whether a policy includes the fragment declared in `module`.
+    pub fn uses_fragment(&self, module: &str, fragment: &Parsed<Fragment>) -> bool {
+        self.fragments.contains(&fragment_id(module, fragment))
+    }
+
+    pub fn reaches_entity(&self, entity: &str) -> bool {
+        self.entities.contains(entity)
+    }
+
+    pub fn traverses(&self, entity: &str, relationship: &str) -> bool {
+        self.relationships
+            .contains(&(entity.to_string(), relationship.to_string()))
+    }
+
+    pub fn denials(&self, entity: &str, relationship: &str) -> &[Vec<String>] {
+        self.denials
+            .get(&(entity.to_string(), relationship.to_string()))
+            .map_or(&[], |x| x.as_slice())
+    }
+
+    fn visit_rule(
+        &mut self,
+        typechecker: &Typechecker,
+        module: &str,
+        entity: &TcEntity,
+        rule: &Parsed<Rule>,
+    ) {
+        // A universal rule matches every relationship, and then matches again
+        // from wherever it leads
+        if rule.data.relationship.data.value == "*" {
+            self.visit_all(typechecker, entity);
+            return;
+        }
+
+        let Some(relationship) = entity.get_rule(&rule.data.relationship) else {
+            return;
+        };
+
+        let target_name = &relationship.data.entity_name.data.value;
+        let key = (
+            entity.name.clone(),
+            relationship.data.relationship_name.data.value.clone(),
+        );
+        self.entities.insert(target_name.clone());
+
+        let Some(target) = typechecker.entity(target_name) else {
+            self.add_denials(key, rule.data.grants.iter());
+            return;
+        };
+
+        let included = rule
+            .data
+            .include_fragments
+            .iter()
+            .filter_map(|included| {
+                typechecker
+                    .resolve_fragment(module, &included.data.value, &target.name)
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+
+        // The negated grants of included fragments withhold grants from the
+        // rule too
+        let grants = included
+            .iter()
+            .flat_map(|decl| &decl.fragment.data.grants)
+            .chain(&rule.data.grants);
+        self.add_denials(key, grants);
+
+        for decl in included {
+            // Fragments may include each other, so only walk each one once
+            if !self.fragments.insert(decl.id()) {
+                continue;
+            }
+
+            for child in &decl.fragment.data.rules {
+                self.visit_rule(typechecker, decl.module, target, child);
+            }
+        }
+
+        for child in &rule.data.rules {
+            self.visit_rule(typechecker, module, target, child);
+        }
+    }
+
+    /// Marks the relationship `key` as traversed, by a rule withholding the
+    /// negated grants among `grants`.
+    fn add_denials<'g>(
+        &mut self,
+        key: (String, String),
+        grants: impl Iterator<Item = &'g Parsed<Grant>>,
+    ) {
+        let denials = self.denials.entry(key.clone()).or_default();
+        for grant in grants.filter(|x| x.data.negated) {
+            if !denials.contains(&grant.data.grant) {
+                denials.push(grant.data.grant.clone());
+            }
+        }
+
+        self.relationships.insert(key);
+    }
+
+    /// Marks everything reachable from `entity` as used.
+    fn visit_all(&mut self, typechecker: &Typechecker, entity: &TcEntity) {
+        let mut pending = vec![entity];
+        let mut seen = HashSet::<&str>::new();
+
+        while let Some(current) = pending.pop() {
+            if !seen.insert(current.name.as_str()) {
+                continue;
+            }
+
+            self.entities.insert(current.name.clone());
+            for relationship in current.relationships() {
+                let target_name = &relationship.data.entity_name.data.value;
+                self.relationships.insert((
+                    current.name.clone(),
+                    relationship.data.relationship_name.data.value.clone(),
+                ));
+                self.entities.insert(target_name.clone());
+
+                if let Some(target) = typechecker.entity(target_name) {
+                    pending.push(target);
+                }
+            }
+        }
+    }
+}
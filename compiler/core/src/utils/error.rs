@@ -1,26 +1,641 @@
-use std::io;
+use std::{fmt::Display, io, path::PathBuf};
 
 use lalrpop_util::{lexer::Token, ParseError};
+use serde::Serialize;
 
-use crate::parser::{parse_location::Parsed, schema_ast::Relationship};
+use crate::parser::parse_location::{DescendableChildren, Parsed};
+
+/// A syntax error, along with the tokens that would have been accepted in
+/// its place.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SyntaxError {
+    pub loc: (usize, usize),
+    pub found: Option<String>,
+    pub expected: Vec<String>,
+    pub message: Option<&'static str>,
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(message) = self.message {
+            return match &self.found {
+                Some(found) => write!(f, "{} `{}`", message, found),
+                None => write!(f, "{}", message),
+            };
+        }
+
+        match &self.found {
+            Some(found) => write!(f, "Unexpected `{}`", found)?,
+            None => write!(f, "Unexpected end of file")?,
+        };
+
+        match self.expected.as_slice() {
+            [] => Ok(()),
+            [expected] => write!(f, ", expected {}", expected),
+            expected => write!(f, ", expected one of {}", expected.join(", ")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A location related to an error, e.g. the original definition that a
+/// duplicate conflicts with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Related {
+    /// The file the location is in, when it isn't the file of the error
+    pub file: Option<PathBuf>,
+    pub loc: Option<(usize, usize)>,
+    pub message: String,
+}
+
+/// A single replacement of the text at `loc`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub loc: (usize, usize),
+    pub replacement: String,
+}
+
+/// Edits that resolve an error, and that are safe to apply without review.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub message: String,
+    pub edits: Vec<Edit>,
+}
+
+/// Every problem the typechecker can find. The code of each kind is stable,
+/// so it can be referenced from documentation and by tools.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypecheckErrorKind {
+    UnknownEntity {
+        entity: String,
+    },
+    UnknownRelationship {
+        entity: String,
+        relationship: String,
+    },
+    InvalidGrant {
+        entity: String,
+        grant: String,
+    },
+    UnknownFragment {
+        fragment: String,
+        entity: String,
+    },
+    UnknownAttribute {
+        entity: String,
+        relationship: String,
+        attribute: String,
+        suggestion: Option<String>,
+    },
+    AttributeArity {
+        attribute: String,
+        expected: usize,
+        found: usize,
+    },
+    InvalidAttributeArgument {
+        attribute: String,
+        argument: String,
+        expected: String,
+        suggestion: Option<String>,
+    },
+    UnknownModule {
+        module: String,
+    },
+    ModuleNotImported {
+        module: String,
+    },
+    AmbiguousName {
+        name: String,
+        modules: Vec<String>,
+    },
+    DuplicateImport {
+        alias: String,
+    },
+    DuplicateRelationship {
+        relationship: String,
+        target: String,
+        original_target: String,
+    },
+    DuplicateGrant {
+        grant: String,
+        entity: Option<String>,
+    },
+    DuplicateFragment {
+        fragment: String,
+        entity: String,
+    },
+    IneffectiveDenial {
+        grant: String,
+    },
+    UnusedFragment {
+        fragment: String,
+        entity: String,
+    },
+    UnreachableEntity {
+        entity: String,
+    },
+    DeadRelationship {
+        entity: String,
+        relationship: String,
+    },
+    RedundantGrant {
+        grant: String,
+        covered_by: String,
+        fragment: Option<String>,
+    },
+    UndeclaredImpliedGrant {
+        entity: String,
+        grant: String,
+        implied: String,
+    },
+    UnboundedRecursion {
+        fragment: String,
+        entity: String,
+        cycle: String,
+    },
+    UndeclaredUniversalGrant {
+        entity: String,
+        grant: String,
+    },
+    UnknownContextVariable {
+        variable: String,
+        suggestion: Option<String>,
+    },
+    InvalidConditionValue {
+        variable: String,
+        value: String,
+        expected: String,
+        suggestion: Option<String>,
+    },
+    InvalidConditionOperator {
+        variable: String,
+        operator: String,
+        kind: String,
+    },
+    DuplicateContextVariable {
+        variable: String,
+    },
+    ShadowedImport {
+        name: String,
+        module: String,
+    },
+}
+
+impl TypecheckErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypecheckErrorKind::UnknownEntity { .. } => "RUU0002",
+            TypecheckErrorKind::UnknownRelationship { .. } => "RUU0003",
+            TypecheckErrorKind::InvalidGrant { .. } => "RUU0004",
+            TypecheckErrorKind::UnknownFragment { .. } => "RUU0005",
+            TypecheckErrorKind::UnknownAttribute { .. } => "RUU0006",
+            TypecheckErrorKind::AttributeArity { .. } => "RUU0007",
+            TypecheckErrorKind::InvalidAttributeArgument { .. } => "RUU0008",
+            TypecheckErrorKind::UnknownModule { .. } => "RUU0009",
+            TypecheckErrorKind::ModuleNotImported { .. } => "RUU0010",
+            TypecheckErrorKind::AmbiguousName { .. } => "RUU0011",
+            TypecheckErrorKind::DuplicateImport { .. } => "RUU0012",
+            TypecheckErrorKind::DuplicateRelationship { .. } => "RUU0013",
+            TypecheckErrorKind::DuplicateGrant { .. } => "RUU0014",
+            TypecheckErrorKind::DuplicateFragment { .. } => "RUU0015",
+            TypecheckErrorKind::IneffectiveDenial { .. } => "RUU0016",
+            TypecheckErrorKind::UnusedFragment { .. } => "RUU0017",
+            TypecheckErrorKind::UnreachableEntity { .. } => "RUU0018",
+            TypecheckErrorKind::DeadRelationship { .. } => "RUU0019",
+            TypecheckErrorKind::RedundantGrant { .. } => "RUU0020",
+            TypecheckErrorKind::UndeclaredImpliedGrant { .. } => "RUU0021",
+            TypecheckErrorKind::UnboundedRecursion { .. } => "RUU0022",
+            TypecheckErrorKind::UndeclaredUniversalGrant { .. } => "RUU0023",
+            TypecheckErrorKind::UnknownContextVariable { .. } => "RUU0024",
+            TypecheckErrorKind::InvalidConditionValue { .. } => "RUU0025",
+            TypecheckErrorKind::InvalidConditionOperator { .. } => "RUU0026",
+            TypecheckErrorKind::DuplicateContextVariable { .. } => "RUU0027",
+            TypecheckErrorKind::ShadowedImport { .. } => "RUU0028",
+        }
+    }
+
+    /// The name of the lint that reports this kind, if it can be allowed
+    /// or have its level changed. Lints are warnings by default, unless
+    /// they are only reported on request.
+    pub fn lint(&self) -> Option<&'static str> {
+        match self {
+            // Repeating a grant has no effect on what is granted
+            TypecheckErrorKind::DuplicateGrant { .. } => Some("duplicate-grant"),
+            TypecheckErrorKind::UnusedFragment { .. } => Some("unused-fragment"),
+            TypecheckErrorKind::UnreachableEntity { .. } => Some("unreachable-entity"),
+            TypecheckErrorKind::DeadRelationship { .. } => Some("dead-relationship"),
+            TypecheckErrorKind::RedundantGrant { .. } => Some("redundant-grant"),
+            TypecheckErrorKind::UnboundedRecursion { .. } => Some("unbounded-recursion"),
+            // The local declaration is the one unqualified names refer to
+            TypecheckErrorKind::ShadowedImport { .. } => Some("shadowed-import"),
+            _ => None,
+        }
+    }
+
+    /// Whether this lint is only reported when the config asks for it.
+    pub fn allowed_by_default(&self) -> bool {
+        // Recursion through relationships is often intended
+        matches!(self, TypecheckErrorKind::UnboundedRecursion { .. })
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self.lint() {
+            Some(_) => Severity::Warning,
+            None => Severity::Error,
+        }
+    }
+}
+
+impl Display for TypecheckErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypecheckErrorKind::UnknownEntity { entity } => {
+                write!(f, "Unable to find entity name: {}", entity)
+            }
+            TypecheckErrorKind::UnknownRelationship {
+                entity,
+                relationship,
+            } => write!(
+                f,
+                "Relationship {} not found for entity {}",
+                relationship, entity
+            ),
+            TypecheckErrorKind::InvalidGrant { entity, grant } => {
+                write!(f, "Entity {} does not allow grant: {}", entity, grant)
+            }
+            TypecheckErrorKind::UnknownFragment { fragment, entity } => write!(
+                f,
+                "Unable to find fragment name: {} for entity {}",
+                fragment, entity
+            ),
+            TypecheckErrorKind::UnknownAttribute {
+                entity,
+                relationship,
+                attribute,
+                suggestion,
+            } => {
+                write!(
+                    f,
+                    "Attribute {} not found for relationship {} of entity {}",
+                    attribute, relationship, entity
+                )?;
+
+                match suggestion {
+                    Some(suggestion) => write!(f, ". Did you mean {}?", suggestion),
+                    None => Ok(()),
+                }
+            }
+            TypecheckErrorKind::AttributeArity {
+                attribute,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Attribute {} takes {} argument(s) but {} were given",
+                attribute, expected, found
+            ),
+            TypecheckErrorKind::InvalidAttributeArgument {
+                attribute,
+                argument,
+                expected,
+                suggestion,
+            } => {
+                write!(
+                    f,
+                    "Invalid argument {} for attribute {}. Expected {}",
+                    argument, attribute, expected
+                )?;
+
+                match suggestion {
+                    Some(suggestion) => write!(f, ". Did you mean {}?", suggestion),
+                    None => Ok(()),
+                }
+            }
+            TypecheckErrorKind::UnknownModule { module } => {
+                write!(f, "Unable to find module: {}", module)
+            }
+            TypecheckErrorKind::ModuleNotImported { module } => write!(
+                f,
+                "Module {} is not imported. Add `import {};` to use it",
+                module, module
+            ),
+            TypecheckErrorKind::AmbiguousName { name, modules } => write!(
+                f,
+                "{} is declared in modules {}. Import one of them and qualify the name, e.g. {}::{}",
+                name,
+                modules.join(", "),
+                modules[0],
+                name
+            ),
+            TypecheckErrorKind::DuplicateImport { alias } => {
+                write!(f, "A module is already imported as {}", alias)
+            }
+            TypecheckErrorKind::DuplicateRelationship {
+                relationship,
+                target,
+                original_target,
+            } => match target == original_target {
+                true => write!(f, "Relationship {} is already declared", relationship),
+                false => write!(
+                    f,
+                    "Relationship {} -> {} conflicts with an earlier declaration -> {}",
+                    relationship, target, original_target
+                ),
+            },
+            TypecheckErrorKind::DuplicateGrant { grant, entity } => match entity {
+                Some(entity) => write!(
+                    f,
+                    "Grant {} is already declared for entity {}",
+                    grant, entity
+                ),
+                None => write!(f, "Grant {} is already declared", grant),
+            },
+            TypecheckErrorKind::DuplicateFragment { fragment, entity } => write!(
+                f,
+                "Fragment {} is already declared for entity {}",
+                fragment, entity
+            ),
+            TypecheckErrorKind::IneffectiveDenial { grant } => write!(
+                f,
+                "Negated grant !{} has no effect, as nothing granted by this rule includes {}",
+                grant, grant
+            ),
+            TypecheckErrorKind::UnusedFragment { fragment, entity } => write!(
+                f,
+                "Fragment {} for entity {} is never included by any policy",
+                fragment, entity
+            ),
+            TypecheckErrorKind::UnreachableEntity { entity } => write!(
+                f,
+                "Entity {} is not reachable from any entrypoint",
+                entity
+            ),
+            TypecheckErrorKind::DeadRelationship {
+                entity,
+                relationship,
+            } => write!(
+                f,
+                "Relationship {} of entity {} is never traversed by any policy",
+                relationship, entity
+            ),
+            TypecheckErrorKind::RedundantGrant {
+                grant,
+                covered_by,
+                fragment,
+            } => match fragment {
+                Some(fragment) => write!(
+                    f,
+                    "Grant {} is redundant, as fragment {} already grants {}",
+                    grant, fragment, covered_by
+                ),
+                None => write!(
+                    f,
+                    "Grant {} is redundant, as {} is already granted",
+                    grant, covered_by
+                ),
+            },
+            TypecheckErrorKind::UndeclaredImpliedGrant {
+                entity,
+                grant,
+                implied,
+            } => write!(
+                f,
+                "Grant {} implies {}, which is not declared for entity {}",
+                grant, implied, entity
+            ),
+            TypecheckErrorKind::UnboundedRecursion {
+                fragment,
+                entity,
+                cycle,
+            } => write!(
+                f,
+                "Fragment {} for entity {} includes itself through {}, so it applies at any depth",
+                fragment, entity, cycle
+            ),
+            TypecheckErrorKind::UndeclaredUniversalGrant { entity, grant } => write!(
+                f,
+                "Universal rules grant {} on entity {}, which does not declare it",
+                grant, entity
+            ),
+            TypecheckErrorKind::UnknownContextVariable {
+                variable,
+                suggestion,
+            } => {
+                write!(f, "Unable to find context variable: {}", variable)?;
+
+                match suggestion {
+                    Some(suggestion) => write!(f, ". Did you mean {}?", suggestion),
+                    None => Ok(()),
+                }
+            }
+            TypecheckErrorKind::InvalidConditionValue {
+                variable,
+                value,
+                expected,
+                suggestion,
+            } => {
+                write!(
+                    f,
+                    "Invalid value {} for context variable {}. Expected {}",
+                    value, variable, expected
+                )?;
+
+                match suggestion {
+                    Some(suggestion) => write!(f, ". Did you mean {}?", suggestion),
+                    None => Ok(()),
+                }
+            }
+            TypecheckErrorKind::InvalidConditionOperator {
+                variable,
+                operator,
+                kind,
+            } => write!(
+                f,
+                "Operator {} only compares int values, but context variable {} is {}",
+                operator, variable, kind
+            ),
+            TypecheckErrorKind::DuplicateContextVariable { variable } => {
+                write!(f, "Context variable {} is already declared", variable)
+            }
+            TypecheckErrorKind::ShadowedImport { name, module } => write!(
+                f,
+                "{} hides the declaration of the same name imported from {}",
+                name, module
+            ),
+        }
+    }
+}
+
+/// A problem found by the typechecker, located at the offending definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypecheckError {
+    pub loc: Option<(usize, usize)>,
+    pub kind: TypecheckErrorKind,
+    pub severity: Severity,
+    pub related: Vec<Related>,
+    pub fix: Option<Fix>,
+}
+
+impl TypecheckError {
+    pub fn new<T: for<'a> DescendableChildren<'a>>(
+        at: &Parsed<T>,
+        kind: TypecheckErrorKind,
+    ) -> Self {
+        Self {
+            loc: at.loc,
+            severity: kind.severity(),
+            kind,
+            related: vec![],
+            fix: None,
+        }
+    }
+
+    /// Points at `at`, in the file it was read from when that's known.
+    pub fn with_related<T: for<'a> DescendableChildren<'a>>(
+        mut self,
+        at: &Parsed<T>,
+        message: String,
+    ) -> Self {
+        self.related.push(Related {
+            file: at.file_name.clone(),
+            loc: at.loc,
+            message,
+        });
+
+        self
+    }
+
+    /// Offers to delete `at`. Nodes without a location, such as the grants
+    /// of a universal rule, can't be removed.
+    pub fn with_removal<T: for<'a> DescendableChildren<'a>>(
+        mut self,
+        at: &Parsed<T>,
+        message: String,
+    ) -> Self {
+        self.fix = at.loc.map(|loc| Fix {
+            message,
+            edits: vec![Edit {
+                loc,
+                replacement: String::new(),
+            }],
+        });
+
+        self
+    }
+}
+
+impl From<TypecheckError> for RuuLangError {
+    fn from(value: TypecheckError) -> Self {
+        RuuLangError::TypecheckError(Box::new(value))
+    }
+}
 
 #[derive(Debug, Clone)]
-pub enum TypecheckError {
-    DuplicateRelationship(Parsed<Relationship>),
-    GeneralError(Parsed<String>),
+pub enum EvaluationError {
+    UnknownEntity(String),
+    UnknownRelationship(String, String),
+}
+
+impl Display for EvaluationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvaluationError::UnknownEntity(entity) => write!(f, "Unknown entity: {}", entity),
+            EvaluationError::UnknownRelationship(entity, relationship) => write!(
+                f,
+                "Relationship {} not found for entity {}",
+                relationship, entity
+            ),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum RuuLangError {
     FileNotFound(String),
     SerdeParseError(toml::de::Error),
-    RuuLangParseError(usize),
-    TypecheckError(TypecheckError),
+    RuuLangParseError(SyntaxError),
+    TypecheckError(Box<TypecheckError>),
+    EvaluationError(EvaluationError),
     Other(&'static str),
 }
 
 pub type Result<T> = std::result::Result<T, RuuLangError>;
 
+impl RuuLangError {
+    /// A stable code identifying the kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuuLangError::RuuLangParseError(_) => "RUU0001",
+            RuuLangError::TypecheckError(error) => error.kind.code(),
+            RuuLangError::FileNotFound(_) => "RUU0100",
+            RuuLangError::SerdeParseError(_) => "RUU0101",
+            RuuLangError::EvaluationError(EvaluationError::UnknownEntity(_)) => "RUU0102",
+            RuuLangError::EvaluationError(EvaluationError::UnknownRelationship(..)) => "RUU0103",
+            RuuLangError::Other(_) => "RUU0199",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            RuuLangError::TypecheckError(error) => error.severity,
+            _ => Severity::Error,
+        }
+    }
+
+    /// Where in its file the error occurred, if it is tied to a file
+    pub fn loc(&self) -> Option<(usize, usize)> {
+        match self {
+            RuuLangError::RuuLangParseError(error) => Some(error.loc),
+            RuuLangError::TypecheckError(error) => error.loc,
+            _ => None,
+        }
+    }
+
+    pub fn related(&self) -> &[Related] {
+        match self {
+            RuuLangError::TypecheckError(error) => &error.related,
+            _ => &[],
+        }
+    }
+
+    pub fn fix(&self) -> Option<&Fix> {
+        match self {
+            RuuLangError::TypecheckError(error) => error.fix.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl Display for RuuLangError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuuLangError::FileNotFound(message) => write!(f, "{}", message),
+            RuuLangError::SerdeParseError(error) => write!(f, "{}", error),
+            RuuLangError::RuuLangParseError(error) => write!(f, "{}", error),
+            RuuLangError::TypecheckError(error) => write!(f, "{}", error.kind),
+            RuuLangError::EvaluationError(error) => write!(f, "{}", error),
+            RuuLangError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
 impl From<io::Error> for RuuLangError {
     fn from(value: io::Error) -> Self {
         RuuLangError::FileNotFound(value.to_string())
@@ -33,18 +648,65 @@ impl From<toml::de::Error> for RuuLangError {
     }
 }
 
-impl<'a> From<ParseError<usize, Token<'a>, &'static str>> for RuuLangError {
+impl From<SyntaxError> for RuuLangError {
+    fn from(value: SyntaxError) -> Self {
+        RuuLangError::RuuLangParseError(value)
+    }
+}
+
+impl<'a> From<ParseError<usize, Token<'a>, &'static str>> for SyntaxError {
     fn from(value: ParseError<usize, Token<'a>, &'static str>) -> Self {
-        match value {
-            ParseError::ExtraToken { token: (start, ..) } => RuuLangError::RuuLangParseError(start),
-            ParseError::InvalidToken { location } => RuuLangError::RuuLangParseError(location),
-            ParseError::UnrecognizedEOF { location, .. } => {
-                RuuLangError::RuuLangParseError(location)
+        let (loc, found, expected, message) = match value {
+            ParseError::ExtraToken {
+                token: (start, token, end),
+            } => ((start, end), Some(token.1), vec![], None),
+            ParseError::InvalidToken { location } => (
+                (location, location + 1),
+                None,
+                vec![],
+                Some("Invalid token"),
+            ),
+            ParseError::UnrecognizedEOF { location, expected } => {
+                ((location, location), None, expected, None)
             }
             ParseError::UnrecognizedToken {
-                token: (start, ..), ..
-            } => RuuLangError::RuuLangParseError(start),
-            ParseError::User { error } => RuuLangError::Other(error),
+                token: (start, token, end),
+                expected,
+            } => ((start, end), Some(token.1), expected, None),
+            ParseError::User { error } => ((0, 0), None, vec![], Some(error)),
+        };
+
+        let mut described = Vec::<String>::new();
+        for token in expected.iter().map(|x| describe_token(x)) {
+            if !described.contains(&token) {
+                described.push(token);
+            }
         }
+
+        SyntaxError {
+            loc,
+            found: found.map(|x| x.to_string()),
+            expected: described,
+            message,
+        }
+    }
+}
+
+impl<'a> From<ParseError<usize, Token<'a>, &'static str>> for RuuLangError {
+    fn from(value: ParseError<usize, Token<'a>, &'static str>) -> Self {
+        RuuLangError::RuuLangParseError(SyntaxError::from(value))
+    }
+}
+
+/// LALRPOP describes expected terminals by their pattern in the grammar,
+/// e.g. `";"`. Literal tokens are shown in backticks, and the patterns
+/// for names and numbers are replaced with a description.
+fn describe_token(token: &str) -> String {
+    match token.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+        Some(literal) => format!("`{}`", literal.replace("\\\"", "\"")),
+        None if token.contains("[0-9]+") => "a number".to_string(),
+        None if token.contains("[a-zA-Z]") => "a name".to_string(),
+        None if token.contains("Comment") => "a comment".to_string(),
+        None => token.to_string(),
     }
 }
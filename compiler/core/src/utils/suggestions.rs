@@ -0,0 +1,37 @@
+/// Finds the candidate closest to `name`, as long as it is close enough to
+/// plausibly be a typo.
+pub fn closest_match<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between two strings.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a_chr) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b_chr) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_chr != *b_chr);
+            let insertion = current[j] + 1;
+            let deletion = previous[j + 1] + 1;
+
+            current.push(substitution.min(insertion).min(deletion));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
@@ -40,6 +40,21 @@ where
     pub fn contains_suffix(&self, path: &Vec<T>) -> bool {
         self.root.contains_suffix(path)
     }
+
+    /// The values of every path in the trie that strictly extends `path`.
+    pub fn values_under(&self, path: &[T]) -> Vec<&U> {
+        let mut node = &self.root;
+        for el in path {
+            match node.children.get(el) {
+                Some(child) => node = child,
+                None => return vec![],
+            }
+        }
+
+        let mut values = vec![];
+        node.collect_values(&mut values);
+        values
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -123,6 +138,13 @@ where
         return self.contains_helper(path, false, true);
     }
 
+    fn collect_values<'a>(&'a self, values: &mut Vec<&'a U>) {
+        for child in self.children.values() {
+            values.extend(child.value.as_ref());
+            child.collect_values(values);
+        }
+    }
+
     fn contains_helper(&self, path: &Vec<T>, allow_prefix: bool, allow_suffix: bool) -> bool {
         let head = path.first();
         let tail = path[1..].to_vec();
@@ -135,7 +157,7 @@ where
         let child = self.children.get(&el);
 
         if child.is_none() {
-            return allow_suffix;
+            return false;
         }
 
         let child = child.unwrap();
@@ -147,6 +169,10 @@ where
 
             return true;
         } else {
+            if allow_suffix && child.value.is_some() {
+                return true;
+            }
+
             return child.contains_helper(&tail, allow_prefix, allow_suffix);
         }
     }
@@ -0,0 +1,322 @@
+use std::ptr;
+
+use crate::{
+    parser::{
+        parse_location::{Context, Descendable, Parsed},
+        ruulang_ast::{Rule, RuuLangFile},
+        schema_ast::Entity,
+    },
+    typechecker::{tc_ast::TcEntity, typechecker::Typechecker},
+    utils::with_origin::WithOrigin,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Entity,
+    Relationship,
+    Attribute,
+    Grant,
+    Fragment,
+}
+
+/// A name that can be written at the cursor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+
+    /// The docstring of the declaration
+    pub detail: Option<String>,
+
+    /// The text to insert, when it isn't the label
+    pub insert_text: Option<String>,
+}
+
+impl Completion {
+    fn new(label: String, kind: CompletionKind, detail: &Option<String>) -> Self {
+        Completion {
+            label,
+            kind,
+            detail: detail.as_ref().map(|x| x.trim().to_string()),
+            insert_text: None,
+        }
+    }
+}
+
+/// The names that can be written at `offset` in `file`, whose text is
+/// `source`.
+///
+/// Inside a rule these are the relationships, grants and fragments of the
+/// entity the rule reaches. After `:` they are the attributes of the rule's
+/// relationship, after `#` the fragments of the entity, and after `@` the
+/// entities themselves.
+pub fn completions(
+    typechecker: &Typechecker,
+    entities: &[WithOrigin<Parsed<Entity>>],
+    file: &RuuLangFile,
+    source: &str,
+    offset: usize,
+) -> Vec<Completion> {
+    let completer = Completer {
+        typechecker,
+        file,
+        source,
+    };
+
+    let Some(before) = source.get(..offset) else {
+        return vec![];
+    };
+
+    // The name being typed may already be partly written
+    let start = before
+        .trim_end_matches(|c| is_name_char(c) || c == '.')
+        .len();
+    let before = &before[..start];
+
+    if before.ends_with('@') {
+        return completer.entities(entities);
+    }
+
+    // Qualified names aren't completed
+    if before.ends_with("::") {
+        return vec![];
+    }
+
+    let scope = completer.scope(offset);
+
+    if before.ends_with(':') {
+        // The rule being written may not have parsed yet, in which case its
+        // relationship is the first name of the statement
+        let (source, relationship) = match scope.header {
+            Some(header) => header,
+            None => {
+                let statement = &before[before.rfind(['{', '}', ';']).map_or(0, |x| x + 1)..];
+                let statement = statement.trim_start();
+                let name = &statement
+                    [..statement.len() - statement.trim_start_matches(is_name_char).len()];
+
+                match scope.entity {
+                    Some(entity) => (entity, name.to_string()),
+                    None => return vec![],
+                }
+            }
+        };
+
+        return completer.attributes(source, &relationship);
+    }
+
+    match (scope.header, scope.entity) {
+        (Some((source, _)), _) => completer.relationships(source),
+        (None, Some(entity)) if before.ends_with('#') => completer.fragments(entity, false),
+        (None, Some(entity)) => [
+            completer.relationships(entity),
+            completer.grants(entity),
+            completer.fragments(entity, true),
+        ]
+        .concat(),
+        (None, None) => vec![],
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Where the cursor is among the entrypoints, fragments and rules of a file.
+#[derive(Default)]
+struct Scope<'t> {
+    /// The entity whose relationships and grants apply at the cursor
+    entity: Option<&'t TcEntity>,
+
+    /// When the cursor is before the `{` of a rule, the entity the rule
+    /// starts from and the rule's relationship
+    header: Option<(&'t TcEntity, String)>,
+}
+
+struct Completer<'t, 'a> {
+    typechecker: &'t Typechecker<'a>,
+    file: &'t RuuLangFile,
+    source: &'t str,
+}
+
+impl<'t, 'a> Completer<'t, 'a> {
+    /// Follows the cursor's descent through the file, starting at the entity
+    /// of its entrypoint or fragment, and moving along the relationship of
+    /// each rule around it.
+    fn scope(&self, offset: usize) -> Scope<'t> {
+        let module = self.file.module.as_str();
+        let stack = self.file.descend_at((offset, offset)).unwrap_or_default();
+        let mut scope = Scope::default();
+
+        for descent in stack {
+            match descent.context {
+                Context::Entrypoint(entrypoint) => {
+                    let reference = &entrypoint.entrypoint.data.value;
+                    scope.entity = self.typechecker.resolve_entity(module, reference).ok();
+                }
+
+                Context::Fragment(fragment) => {
+                    let reference = &fragment.for_entity.data.value;
+                    scope.entity = self.typechecker.resolve_entity(module, reference).ok();
+                }
+
+                Context::Rule(rule) => {
+                    let Some(source) = scope.entity else {
+                        break;
+                    };
+
+                    let relationship = &rule.relationship.data.value;
+                    if self.in_header(rule, offset) {
+                        scope.header = Some((source, relationship.clone()));
+                        break;
+                    }
+
+                    scope.entity = source
+                        .get_rule(relationship)
+                        .and_then(|x| self.typechecker.entity(&x.data.entity_name.data.value));
+                }
+
+                _ => {}
+            }
+        }
+
+        scope
+    }
+
+    /// Whether `offset` is before the `{` that opens the body of `rule`.
+    fn in_header(&self, rule: &Rule, offset: usize) -> bool {
+        let Some((_, end)) = rule.relationship.loc else {
+            return false;
+        };
+
+        let body = self.source.get(end..).and_then(|x| x.find('{'));
+        body.map_or(true, |body| offset <= end + body)
+    }
+
+    /// How a name can be written in the file, unqualified if that refers to
+    /// the declaration, or otherwise qualified by one of the file's imports.
+    fn written_name(&self, name: &str, resolves: impl Fn(&str) -> bool) -> Option<String> {
+        std::iter::once(name.to_string())
+            .chain(
+                self.file
+                    .imports
+                    .iter()
+                    .map(|x| format!("{}::{}", x.data.name(), name)),
+            )
+            .find(|x| resolves(x))
+    }
+
+    fn entities(&self, entities: &[WithOrigin<Parsed<Entity>>]) -> Vec<Completion> {
+        let module = self.file.module.as_str();
+        let modules = self.typechecker.modules();
+        let mut completions: Vec<Completion> = vec![];
+
+        for entity in entities {
+            let name = &entity.data.data.name.data.value;
+            let resolved = modules.entity_name(modules.module_of(&entity.origin), name);
+
+            let Some(label) = self.written_name(name, |x| {
+                self.typechecker
+                    .resolve_entity(module, x)
+                    .is_ok_and(|x| x.name == resolved)
+            }) else {
+                continue;
+            };
+
+            // An entity declared in parts is suggested once, with the first
+            // docstring found
+            match completions.iter_mut().find(|x| x.label == label) {
+                Some(existing) => {
+                    if existing.detail.is_none() {
+                        existing.detail = entity.data.docstring.clone();
+                    }
+                }
+                None => completions.push(Completion::new(
+                    label,
+                    CompletionKind::Entity,
+                    &entity.data.docstring,
+                )),
+            }
+        }
+
+        completions.sort_by(|a, b| a.label.cmp(&b.label));
+        completions
+    }
+
+    fn relationships(&self, entity: &TcEntity) -> Vec<Completion> {
+        let mut completions = entity
+            .relationships()
+            .map(|x| {
+                Completion::new(
+                    x.data.relationship_name.data.value.clone(),
+                    CompletionKind::Relationship,
+                    &x.docstring,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        completions.sort_by(|a, b| a.label.cmp(&b.label));
+        completions
+    }
+
+    fn attributes(&self, entity: &TcEntity, relationship: &String) -> Vec<Completion> {
+        let Some(relationship) = entity.get_rule(relationship) else {
+            return vec![];
+        };
+
+        relationship
+            .data
+            .attributes
+            .iter()
+            .map(|x| {
+                Completion::new(
+                    x.data.name.data.value.clone(),
+                    CompletionKind::Attribute,
+                    &x.docstring,
+                )
+            })
+            .collect()
+    }
+
+    fn grants(&self, entity: &TcEntity) -> Vec<Completion> {
+        let mut completions = entity
+            .grants()
+            .into_iter()
+            .map(|x| Completion::new(x.data.grant.join("."), CompletionKind::Grant, &x.docstring))
+            .collect::<Vec<_>>();
+
+        completions.sort_by(|a, b| a.label.cmp(&b.label));
+        completions
+    }
+
+    /// The fragments that can be included for `entity`. Unless the `#` has
+    /// already been written, inserting one writes the whole include.
+    fn fragments(&self, entity: &TcEntity, with_include: bool) -> Vec<Completion> {
+        let module = self.file.module.as_str();
+
+        let mut completions = self
+            .typechecker
+            .fragments()
+            .filter(|(for_entity, _)| *for_entity == entity.name)
+            .filter_map(|(_, decl)| {
+                let name = &decl.fragment.data.name.data.value;
+                let label = self.written_name(name, |x| {
+                    self.typechecker
+                        .resolve_fragment(module, x, &entity.name)
+                        .is_ok_and(|x| ptr::eq(x.fragment, decl.fragment))
+                })?;
+
+                let mut completion =
+                    Completion::new(label, CompletionKind::Fragment, &decl.fragment.docstring);
+                if with_include {
+                    completion.insert_text = Some(format!("#{};", completion.label));
+                }
+
+                Some(completion)
+            })
+            .collect::<Vec<_>>();
+
+        completions.sort_by(|a, b| a.label.cmp(&b.label));
+        completions
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{parser::parser_constructs::ParserStatement, utils::error::Edit};
+
+use super::symbols::{Symbol, SymbolRef};
+
+/// What renaming the name under the cursor changes.
+///
+/// Grants are renamed one segment at a time. Renaming `basic` in
+/// `read.basic` also renames the grants beneath it, such as
+/// `read.basic.email`, but leaves `read` alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameTarget {
+    pub symbol: Symbol,
+
+    /// The part of the name under the cursor that is replaced
+    pub loc: (usize, usize),
+}
+
+impl RenameTarget {
+    /// What renaming at `offset`, within the name `reference` of `source`,
+    /// changes.
+    pub fn new(source: &str, reference: &SymbolRef, offset: usize) -> Option<Self> {
+        match &reference.symbol {
+            Symbol::Grant { entity, grant } => {
+                let segments = grant_segments(source, reference.loc, grant)?;
+                let index = segments
+                    .iter()
+                    .position(|(start, end)| *start <= offset && offset <= *end)?;
+
+                Some(RenameTarget {
+                    symbol: Symbol::Grant {
+                        entity: entity.clone(),
+                        grant: grant[..=index].to_vec(),
+                    },
+                    loc: segments[index],
+                })
+            }
+
+            symbol => Some(RenameTarget {
+                symbol: symbol.clone(),
+                loc: name_span(source, reference.loc)?,
+            }),
+        }
+    }
+
+    /// Whether renaming the target changes any part of `symbol`.
+    pub fn renames(&self, symbol: &Symbol) -> bool {
+        match (&self.symbol, symbol) {
+            (
+                Symbol::Grant {
+                    entity,
+                    grant: prefix,
+                },
+                Symbol::Grant {
+                    entity: other,
+                    grant,
+                },
+            ) => entity == other && grant.starts_with(prefix),
+            (target, symbol) => target == symbol,
+        }
+    }
+
+    /// The edit that gives `reference`, a name in `source`, its new name.
+    /// Only the last segment of a qualified name is replaced, so the
+    /// qualifier is kept.
+    pub fn edit(&self, source: &str, reference: &SymbolRef, name: &str) -> Option<Edit> {
+        let loc = match (&self.symbol, &reference.symbol) {
+            (Symbol::Grant { grant: prefix, .. }, Symbol::Grant { grant, .. }) => {
+                *grant_segments(source, reference.loc, grant)?.get(prefix.len() - 1)?
+            }
+            _ => name_span(source, reference.loc)?,
+        };
+
+        Some(Edit {
+            loc,
+            replacement: name.to_string(),
+        })
+    }
+}
+
+/// Whether `name` can replace a name, or a segment of a grant.
+///
+/// Keywords are reserved, so rather than keeping a list of them the name is
+/// checked by asking the parser to read it as the name of an entity.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_with_letter = chars.next().is_some_and(|x| x.is_ascii_alphabetic());
+
+    if !starts_with_letter || !chars.all(is_name_char) {
+        return false;
+    }
+
+    match ParserStatement::parse(&format!("entity {name} {{}}")) {
+        Ok((statements, errors)) => {
+            errors.is_empty()
+                && matches!(
+                    statements.as_slice(),
+                    [ParserStatement::Entity(entity)] if entity.data.name.data.value == name
+                )
+        }
+        Err(_) => false,
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// The span of the last segment of the (possibly qualified) name at `loc`.
+fn name_span(source: &str, loc: (usize, usize)) -> Option<(usize, usize)> {
+    let text = source.get(loc.0..loc.1)?;
+    let name = text.rsplit("::").next()?.trim_start();
+
+    Some((loc.1 - name.len(), loc.1))
+}
+
+/// The span of each segment of the grant at `loc`. The span of a grant
+/// covers the whole statement it's part of, so this looks for the first place
+/// its segments appear in order, separated by `.`.
+fn grant_segments(
+    source: &str,
+    loc: (usize, usize),
+    grant: &[String],
+) -> Option<Vec<(usize, usize)>> {
+    let text = source.get(loc.0..loc.1)?;
+
+    text.char_indices()
+        .filter(|(i, _)| !text[..*i].ends_with(is_name_char))
+        .find_map(|(i, _)| segments_from(text, i, grant))
+        .map(|segments| {
+            segments
+                .into_iter()
+                .map(|(start, end)| (loc.0 + start, loc.0 + end))
+                .collect()
+        })
+}
+
+fn segments_from(text: &str, start: usize, grant: &[String]) -> Option<Vec<(usize, usize)>> {
+    let mut segments = vec![];
+    let mut at = start;
+
+    for (i, segment) in grant.iter().enumerate() {
+        if i > 0 {
+            let rest = text[at..].trim_start().strip_prefix('.')?.trim_start();
+            at = text.len() - rest.len();
+        }
+
+        let end = at + segment.len();
+        if !text[at..].starts_with(segment.as_str()) || text[end..].starts_with(is_name_char) {
+            return None;
+        }
+
+        segments.push((at, end));
+        at = end;
+    }
+
+    Some(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_valid_name;
+
+    #[test]
+    fn keywords_are_not_valid_names() {
+        for keyword in [
+            "abstract",
+            "and",
+            "as",
+            "context",
+            "entity",
+            "for",
+            "fragment",
+            "if",
+            "implies",
+            "import",
+            "universal",
+        ] {
+            assert!(!is_valid_name(keyword), "{keyword}");
+        }
+
+        for name in ["user", "read-all", "ifs", "context_id"] {
+            assert!(is_valid_name(name), "{name}");
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use crate::{
+    parser::{
+        parse_location::{DescendableChildren, Identifier, Parsed},
+        ruulang_ast::{Grant, Rule, RuuLangFile},
+    },
+    typechecker::{tc_ast::TcEntity, typechecker::Typechecker},
+    utils::{error::Result, with_origin::WithOrigin},
+};
+
+/// A declaration that names in the workspace can refer to.
+///
+/// Entities are identified by their resolved name, so the parts of an entity
+/// declared more than once are the same symbol. Each module may declare its
+/// own fragment for an entity, so fragments are also identified by the file
+/// declaring them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    Entity(String),
+    Relationship {
+        entity: String,
+        relationship: String,
+    },
+    Attribute {
+        entity: String,
+        relationship: String,
+        attribute: String,
+    },
+    Grant {
+        entity: String,
+        grant: Vec<String>,
+    },
+    Fragment {
+        origin: PathBuf,
+        entity: String,
+        fragment: String,
+    },
+    ContextVariable(String),
+}
+
+/// A name in a file, along with the declaration it resolves to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolRef {
+    pub symbol: Symbol,
+    pub loc: (usize, usize),
+
+    /// Whether the name is part of the declaration, rather than a use of it
+    pub is_declaration: bool,
+}
+
+/// The resolved names of every file in the workspace, so that the uses of a
+/// declaration can be found without resolving the whole workspace again.
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    files: HashMap<PathBuf, IndexedFile>,
+}
+
+#[derive(Debug)]
+struct IndexedFile {
+    symbols: Vec<SymbolRef>,
+
+    /// What the file declares for other files to use, including what each
+    /// declaration resolves to. Names elsewhere in the workspace only resolve
+    /// differently when this changes.
+    exports: Vec<Symbol>,
+}
+
+impl IndexedFile {
+    /// Resolves every name in `file` that refers to a declaration. Names that
+    /// don't resolve, such as unknown relationships, are left out.
+    fn new(typechecker: &Typechecker, origin: &PathBuf, file: &RuuLangFile) -> Self {
+        let mut collector = Collector {
+            typechecker,
+            module: &file.module,
+            origin,
+            exporting: false,
+            file: IndexedFile {
+                symbols: vec![],
+                exports: vec![],
+            },
+        };
+
+        collector.visit_file(file);
+        collector.file
+    }
+
+    /// The declarations the file uses, wherever they are declared.
+    fn uses(&self) -> HashSet<Symbol> {
+        self.symbols
+            .iter()
+            .filter(|x| !x.is_declaration)
+            .map(|x| x.symbol.clone())
+            .collect()
+    }
+}
+
+impl SymbolIndex {
+    pub fn new(typechecker: &Typechecker, files: &[WithOrigin<Result<RuuLangFile>>]) -> Self {
+        let mut index = SymbolIndex::default();
+
+        for schema in files {
+            index.index_file(typechecker, schema);
+        }
+
+        index
+    }
+
+    /// Reindexes the file at `path` after it changed, and returns the files
+    /// whose diagnostics may have changed along with it.
+    ///
+    /// When the file's declarations changed, names anywhere may resolve
+    /// differently, so the whole workspace is reindexed and returned.
+    /// Otherwise only the usage lints of other files may have changed, on
+    /// the declarations the file started or stopped using, and on everything
+    /// those declarations use in turn, along with the errors of the files
+    /// including the file's fragments.
+    pub fn update(
+        &mut self,
+        typechecker: &Typechecker,
+        files: &[WithOrigin<Result<RuuLangFile>>],
+        path: &PathBuf,
+    ) -> Vec<PathBuf> {
+        let previous = self.files.remove(path);
+
+        if let Some(schema) = files.iter().find(|x| &x.origin == path) {
+            self.index_file(typechecker, schema);
+        }
+
+        let (previous_exports, previous_uses) = previous
+            .map(|x| (x.exports.clone(), x.uses()))
+            .unwrap_or_default();
+        let (exports, uses) = self
+            .files
+            .get(path)
+            .map(|x| (x.exports.clone(), x.uses()))
+            .unwrap_or_default();
+
+        if exports != previous_exports {
+            *self = SymbolIndex::new(typechecker, files);
+            return files.iter().map(|x| x.origin.clone()).collect();
+        }
+
+        let changed = previous_uses.symmetric_difference(&uses).cloned().collect();
+        let mut affected = self.declaring(changed);
+        affected.extend(self.including(path));
+        affected.insert(path.clone());
+
+        let mut affected = affected.into_iter().collect::<Vec<_>>();
+        affected.sort();
+        affected
+    }
+
+    /// The files that declare any of `symbols`, along with the files that
+    /// declare what those files use, and so on.
+    fn declaring(&self, symbols: Vec<Symbol>) -> HashSet<PathBuf> {
+        let mut declared_in = HashMap::<&Symbol, Vec<&PathBuf>>::new();
+        for (origin, file) in &self.files {
+            for symbol in file.symbols.iter().filter(|x| x.is_declaration) {
+                declared_in.entry(&symbol.symbol).or_default().push(origin);
+            }
+        }
+
+        let mut files = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut pending = symbols;
+
+        while let Some(symbol) = pending.pop() {
+            if !seen.insert(symbol.clone()) {
+                continue;
+            }
+
+            for origin in declared_in.get(&symbol).into_iter().flatten() {
+                if files.insert((*origin).clone()) {
+                    pending.extend(self.files[*origin].uses());
+                }
+            }
+        }
+
+        files
+    }
+
+    /// The files that include a fragment declared in the file at `path`,
+    /// along with the files including the fragments of those files, and so
+    /// on. What a fragment contains isn't part of what its file exports, but
+    /// it is checked wherever the fragment is included.
+    fn including(&self, path: &PathBuf) -> HashSet<PathBuf> {
+        let mut files = HashSet::new();
+        let mut pending = vec![path];
+
+        while let Some(origin) = pending.pop() {
+            let Some(file) = self.files.get(origin) else {
+                continue;
+            };
+
+            let fragments = file
+                .symbols
+                .iter()
+                .filter(|x| x.is_declaration && matches!(x.symbol, Symbol::Fragment { .. }))
+                .map(|x| &x.symbol)
+                .collect::<HashSet<_>>();
+
+            for (other, other_file) in &self.files {
+                let includes = other_file
+                    .symbols
+                    .iter()
+                    .any(|x| !x.is_declaration && fragments.contains(&x.symbol));
+
+                if includes && files.insert(other.clone()) {
+                    pending.push(other);
+                }
+            }
+        }
+
+        files
+    }
+
+    fn index_file(&mut self, typechecker: &Typechecker, schema: &WithOrigin<Result<RuuLangFile>>) {
+        let Ok(file) = &schema.data else {
+            return;
+        };
+
+        let indexed = IndexedFile::new(typechecker, &schema.origin, file);
+        self.files.insert(schema.origin.clone(), indexed);
+    }
+
+    /// The resolved names in the file at `path`.
+    pub fn symbols(&self, path: &PathBuf) -> &[SymbolRef] {
+        self.files
+            .get(path)
+            .map(|x| x.symbols.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Every name in the workspace that resolves to `symbol`, declarations
+    /// included, ordered by file and then position.
+    pub fn references(&self, symbol: &Symbol) -> Vec<WithOrigin<&SymbolRef>> {
+        self.find(|x| x == symbol)
+    }
+
+    /// Every name in the workspace that resolves to a symbol matching
+    /// `predicate`, ordered by file and then position.
+    pub fn find(&self, predicate: impl Fn(&Symbol) -> bool) -> Vec<WithOrigin<&SymbolRef>> {
+        let mut references = self
+            .files
+            .iter()
+            .flat_map(|(origin, file)| {
+                file.symbols
+                    .iter()
+                    .filter(|x| predicate(&x.symbol))
+                    .map(|x| WithOrigin::new(x, origin.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        references.sort_by(|a, b| (&a.origin, a.data.loc).cmp(&(&b.origin, b.data.loc)));
+        references
+    }
+}
+
+struct Collector<'t, 'a> {
+    typechecker: &'t Typechecker<'a>,
+    module: &'t str,
+    origin: &'t PathBuf,
+
+    /// Whether the names being visited are part of what the file exports
+    exporting: bool,
+    file: IndexedFile,
+}
+
+impl<'t, 'a> Collector<'t, 'a> {
+    fn push<T: for<'b> DescendableChildren<'b>>(
+        &mut self,
+        symbol: Symbol,
+        at: &Parsed<T>,
+        is_declaration: bool,
+    ) {
+        let Some(loc) = at.loc else {
+            return;
+        };
+
+        if self.exporting || is_declaration {
+            self.file.exports.push(symbol.clone());
+        }
+
+        self.file.symbols.push(SymbolRef {
+            symbol,
+            loc,
+            is_declaration,
+        });
+    }
+
+    fn visit_file(&mut self, file: &RuuLangFile) {
+        // Context variables and entities, along with the entities their
+        // relationships lead to, are visible from every file
+        self.exporting = true;
+
+        for variable in &file.context {
+            let symbol = Symbol::ContextVariable(variable.data.name.data.value.clone());
+            self.push(symbol, &variable.data.name, true);
+        }
+
+        for entity in &file.entities {
+            let entity_name = self
+                .typechecker
+                .modules()
+                .entity_name(self.module, &entity.data.name.data.value);
+            self.push(Symbol::Entity(entity_name.clone()), &entity.data.name, true);
+
+            for grant in &entity.data.grants {
+                self.visit_grant(&entity_name, grant, true);
+
+                for implied in &grant.data.implies {
+                    self.visit_grant(&entity_name, implied, false);
+                }
+            }
+
+            for grant in entity.data.universal.iter().flatten() {
+                self.visit_grant(&entity_name, grant, false);
+            }
+
+            for relationship in &entity.data.relationships {
+                let relationship_name = &relationship.data.relationship_name;
+                let symbol = Symbol::Relationship {
+                    entity: entity_name.clone(),
+                    relationship: relationship_name.data.value.clone(),
+                };
+                self.push(symbol, relationship_name, true);
+
+                for attribute in &relationship.data.attributes {
+                    let symbol = Symbol::Attribute {
+                        entity: entity_name.clone(),
+                        relationship: relationship_name.data.value.clone(),
+                        attribute: attribute.data.name.data.value.clone(),
+                    };
+                    self.push(symbol, &attribute.data.name, true);
+                }
+
+                self.visit_entity(&relationship.data.entity_name);
+            }
+        }
+
+        self.exporting = false;
+
+        for fragment in &file.fragments {
+            let Some(entity) = self.visit_entity(&fragment.data.for_entity) else {
+                continue;
+            };
+
+            let symbol = Symbol::Fragment {
+                origin: self.origin.clone(),
+                entity: entity.name.clone(),
+                fragment: fragment.data.name.data.value.clone(),
+            };
+            self.push(symbol, &fragment.data.name, true);
+
+            for grant in &fragment.data.grants {
+                self.visit_grant(&entity.name, grant, false);
+            }
+
+            for rule in &fragment.data.rules {
+                self.visit_rule(entity, rule);
+            }
+        }
+
+        for entrypoint in &file.entrypoints {
+            let Some(entity) = self.visit_entity(&entrypoint.data.entrypoint) else {
+                continue;
+            };
+
+            for rule in &entrypoint.data.rules {
+                self.visit_rule(entity, rule);
+            }
+        }
+    }
+
+    /// Resolves a reference to an entity, returning the entity it refers to.
+    fn visit_entity(&mut self, reference: &Parsed<Identifier>) -> Option<&'t TcEntity> {
+        let entity = self
+            .typechecker
+            .resolve_entity(self.module, &reference.data.value)
+            .ok()?;
+
+        self.push(Symbol::Entity(entity.name.clone()), reference, false);
+        Some(entity)
+    }
+
+    fn visit_grant(&mut self, entity: &str, grant: &Parsed<Grant>, is_declaration: bool) {
+        let symbol = Symbol::Grant {
+            entity: entity.to_string(),
+            grant: grant.data.grant.clone(),
+        };
+        self.push(symbol, grant, is_declaration);
+    }
+
+    /// Resolves the names in a rule that starts from `source`. The rule's
+    /// relationship and attributes belong to `source`, while its grants,
+    /// fragments and nested rules apply to the entity it reaches.
+    fn visit_rule(&mut self, source: &'t TcEntity, rule: &Parsed<Rule>) {
+        for condition in &rule.data.conditions {
+            let variable = &condition.data.variable;
+            let symbol = Symbol::ContextVariable(variable.data.value.clone());
+            self.push(symbol, variable, false);
+        }
+
+        // Universal rules don't name a relationship
+        let Some(relationship) = source.get_rule(&rule.data.relationship.data.value) else {
+            return;
+        };
+
+        let symbol = Symbol::Relationship {
+            entity: source.name.clone(),
+            relationship: rule.data.relationship.data.value.clone(),
+        };
+        self.push(symbol, &rule.data.relationship, false);
+
+        for attribute in &rule.data.attributes {
+            let symbol = Symbol::Attribute {
+                entity: source.name.clone(),
+                relationship: rule.data.relationship.data.value.clone(),
+                attribute: attribute.data.name.data.value.clone(),
+            };
+            self.push(symbol, &attribute.data.name, false);
+        }
+
+        let Some(target) = self
+            .typechecker
+            .entity(&relationship.data.entity_name.data.value)
+        else {
+            return;
+        };
+
+        for grant in &rule.data.grants {
+            self.visit_grant(&target.name, grant, false);
+        }
+
+        for included in &rule.data.include_fragments {
+            let Ok(decl) =
+                self.typechecker
+                    .resolve_fragment(self.module, &included.data.value, &target.name)
+            else {
+                continue;
+            };
+
+            let symbol = Symbol::Fragment {
+                origin: decl.origin.clone(),
+                entity: target.name.clone(),
+                fragment: decl.fragment.data.name.data.value.clone(),
+            };
+            self.push(symbol, included, false);
+        }
+
+        for child in &rule.data.rules {
+            self.visit_rule(target, child);
+        }
+    }
+}
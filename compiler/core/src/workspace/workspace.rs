@@ -1,22 +1,61 @@
-use std::{collections::HashMap, path::PathBuf, str::from_utf8};
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    str::from_utf8,
+    sync::{Mutex, PoisonError},
+};
 
 use crate::{
     codegen::{codegen::Codegen, python::PythonCodegen},
-    config::config::RuuLangConfig,
+    config::config::{LintLevel, RuuLangConfig},
+    diagnostics::{
+        diagnostic::{whole_line, Diagnostic, Severity},
+        emitter::MessageFormat,
+    },
+    evaluator::evaluator::Evaluator,
     parser::{
         assembler::ParserAssemble,
-        parse_location::Parsed,
+        parse_location::{Descendable, Parsed},
         parser_constructs::ParserStatement,
-        ruulang_ast::{Fragment, RuuLangFile},
+        ruulang_ast::{Entrypoint, Fragment, Import, Rule, RuuLangFile},
         schema_ast::{Entity, RuuLangSchema},
     },
-    typechecker::typechecker::Typechecker,
-    utils::error::{Result, RuuLangError, TypecheckError},
+    typechecker::{
+        modules::{split_reference, ModuleIndex},
+        tc_ast::TcEntity,
+        typechecker::{Analysis, Typechecker},
+    },
+    utils::{
+        error::{Edit, Result, RuuLangError, TypecheckError},
+        with_origin::WithOrigin,
+    },
 };
 use async_recursion::async_recursion;
 use tokio::fs;
 
-use crate::utils::with_origin::WithOrigin;
+use super::{
+    completion::{completions, Completion},
+    rename::{is_valid_name, RenameTarget},
+    symbols::{Symbol, SymbolIndex, SymbolRef},
+};
+
+type Policies = (Vec<Parsed<Fragment>>, Vec<Parsed<Entrypoint>>);
+
+/// The referencing file, the reference, and the file and name of the entity
+type FragmentKey = (PathBuf, String, PathBuf, String);
+
+/// The declarations names were resolved to, by their position in the
+/// workspace, so that looking the same name up again doesn't index the
+/// workspace again
+#[derive(Debug, Default)]
+struct Lookups {
+    /// Keyed by the referencing file and the reference
+    entities: HashMap<(PathBuf, String), Option<usize>>,
+
+    /// Values are the index of the declaring file, then of the fragment
+    /// within it
+    fragments: HashMap<FragmentKey, Option<(usize, usize)>>,
+}
 
 #[derive(Debug)]
 pub struct Workspace {
@@ -26,6 +65,17 @@ pub struct Workspace {
     source_files: HashMap<PathBuf, String>,
     entities: Vec<WithOrigin<Parsed<Entity>>>,
     files: Vec<WithOrigin<Result<RuuLangFile>>>,
+    symbols: SymbolIndex,
+
+    /// Kept between edits, and only rebuilt when an edit changes what it
+    /// was worked out from
+    analysis: Analysis,
+
+    /// The errors of each file, as of the last edit that could change them
+    diagnostics: HashMap<PathBuf, Vec<RuuLangError>>,
+
+    /// Cleared whenever a file changes
+    lookups: Mutex<Lookups>,
 }
 
 impl Workspace {
@@ -37,6 +87,10 @@ impl Workspace {
             source_files: HashMap::new(),
             entities: vec![],
             files: vec![],
+            symbols: SymbolIndex::default(),
+            analysis: Analysis::default(),
+            diagnostics: HashMap::new(),
+            lookups: Mutex::default(),
         };
     }
 
@@ -48,6 +102,13 @@ impl Workspace {
         self.entities = entities;
         self.files = files;
         self.source_files = file_data;
+
+        self.diagnostics.clear();
+        self.lookups = Mutex::default();
+
+        let typechecker = Typechecker::new(&self.entities, &self.files);
+        self.analysis = typechecker.analysis();
+        self.symbols = SymbolIndex::new(&typechecker, &self.files);
     }
 
     pub async fn file_is_ruulang_source(&self, path: &PathBuf) -> bool {
@@ -58,10 +119,11 @@ impl Workspace {
     }
 
     pub async fn compile_all(&self) -> Result<()> {
+        let typechecker = self.typechecker();
         let mut maybe_err = None;
 
         for schema in &self.files {
-            let result = self.compile_one(schema).await;
+            let result = self.compile_one(&typechecker, schema).await;
 
             if let Err(err) = result {
                 maybe_err = Some(err);
@@ -75,17 +137,20 @@ impl Workspace {
         }
     }
 
+    /// The errors in the file at `path`. Files are only typechecked again
+    /// when an edit could have changed their errors.
     pub async fn typecheck_file(&self, path: &PathBuf) -> Vec<RuuLangError> {
+        if let Some(errors) = self.diagnostics.get(path) {
+            return errors.clone();
+        }
+
         let schema = self.files.iter().find(|x| &x.origin == path);
 
         match schema {
             Some(schema) => {
-                let typechecker = Typechecker::new(&self.entities, &self.files);
+                let typechecker = self.typechecker();
 
-                match &schema.data {
-                    Ok(data) => typechecker.validate_file(data),
-                    Err(e) => vec![e.clone()],
-                }
+                self.file_errors(&typechecker, schema)
             }
 
             None => vec![RuuLangError::FileNotFound(format!(
@@ -95,48 +160,150 @@ impl Workspace {
         }
     }
 
-    pub async fn typecheck(&self) -> Result<()> {
-        let typechecker = Typechecker::new(&self.entities, &self.files);
+    /// Every error in the workspace, with paths relative to the working
+    /// directory.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let typechecker = self.typechecker();
 
-        let mut total_errors = 0;
+        let mut origins = self.files.iter().collect::<Vec<_>>();
+        origins.sort_by(|a, b| a.origin.cmp(&b.origin));
 
-        for schema in &self.files {
-            match &schema.data {
-                Ok(data) => {
-                    let errors = typechecker.validate_file(data);
-                    total_errors += errors.len();
+        let relative = |path: &PathBuf| {
+            path.strip_prefix(&self.working_dir)
+                .unwrap_or(path)
+                .to_path_buf()
+        };
 
-                    if errors.len() > 0 {
-                        println!("Errors in file: {}", schema.origin.display());
-                    }
+        let mut diagnostics = vec![];
+        for schema in origins {
+            let file = relative(&schema.origin);
 
-                    for error in errors {
-                        if let RuuLangError::TypecheckError(TypecheckError::GeneralError(
-                            general_error,
-                        )) = error
-                        {
-                            println!("    [{:?}] {}", general_error.loc, general_error.data);
-                        }
-                    }
-                }
-                Err(e) => {
-                    total_errors += 1;
+            diagnostics.extend(self.file_errors(&typechecker, schema).iter().map(|error| {
+                let mut diagnostic = Diagnostic::from_error(file.clone(), error);
 
-                    println!("Error parsing file: {:?}", e);
+                // Notes only name their file when it's another one
+                for note in &mut diagnostic.notes {
+                    note.file = note
+                        .file
+                        .take()
+                        .filter(|x| x != &schema.origin)
+                        .map(|x| relative(&x));
                 }
-            }
+
+                diagnostic
+            }));
         }
 
-        println!("Finished Typechecking. {} error(s) found.", total_errors);
-        Ok(())
+        diagnostics
+    }
+
+    /// Writes out every diagnostic in the workspace in the given format, and
+    /// returns the number of errors found.
+    pub async fn typecheck(&self, format: &MessageFormat) -> Result<usize> {
+        let diagnostics = self.diagnostics();
+        let errors = diagnostics
+            .iter()
+            .filter(|x| x.severity == Severity::Error)
+            .count();
+
+        print!(
+            "{}",
+            format.emit(&diagnostics, |file| {
+                self.source_files
+                    .get(&self.working_dir.join(file))
+                    .map(|x| x.as_str())
+            })
+        );
+
+        if format.is_human() {
+            println!("Finished Typechecking. {} error(s) found.", errors);
+        }
+
+        Ok(errors)
+    }
+
+    /// The syntax errors recovered from while parsing a file, followed by
+    /// its type errors. Lints are reported at their configured level, unless
+    /// they are allowed.
+    fn file_errors(
+        &self,
+        typechecker: &Typechecker,
+        schema: &WithOrigin<Result<RuuLangFile>>,
+    ) -> Vec<RuuLangError> {
+        let source = self.source_files.get(&schema.origin);
+
+        match &schema.data {
+            Ok(data) => data
+                .syntax_errors
+                .iter()
+                .cloned()
+                .map(RuuLangError::RuuLangParseError)
+                .chain(typechecker.validate_file(data))
+                .filter_map(|error| self.apply_lint_level(data, error))
+                .map(|mut error| {
+                    if let (Some(source), RuuLangError::TypecheckError(error)) =
+                        (source, &mut error)
+                    {
+                        remove_whole_lines(source, error);
+                    }
+
+                    error
+                })
+                .collect(),
+            Err(e) => vec![e.clone()],
+        }
+    }
+
+    /// Sets the severity of a lint from the config, or drops it when it is
+    /// allowed there or by a `// ruu-allow(...)` comment on an enclosing node.
+    fn apply_lint_level(&self, file: &RuuLangFile, error: RuuLangError) -> Option<RuuLangError> {
+        let RuuLangError::TypecheckError(mut error) = error else {
+            return Some(error);
+        };
+
+        let Some(lint) = error.kind.lint() else {
+            return Some(RuuLangError::TypecheckError(error));
+        };
+
+        let allowed_inline = error
+            .loc
+            .and_then(|(start, _)| file.descend_at((start, start)))
+            .unwrap_or_default()
+            .iter()
+            .any(|x| x.allowed_lints.iter().any(|allowed| allowed == lint));
+
+        if allowed_inline {
+            return None;
+        }
+
+        match self.config.lints.get(lint) {
+            Some(LintLevel::Allow) => return None,
+            Some(LintLevel::Warn) => error.severity = Severity::Warning,
+            Some(LintLevel::Deny) => error.severity = Severity::Error,
+            None if error.kind.allowed_by_default() => return None,
+            None => {}
+        }
+
+        Some(RuuLangError::TypecheckError(error))
+    }
+
+    pub fn evaluator(&self) -> Evaluator<'_> {
+        Evaluator::new(&self.entities, &self.files)
     }
 
-    pub async fn patch_file(&mut self, path: &PathBuf, contents: &String) -> Result<()> {
+    /// Replaces the contents of the file at `path`, and typechecks the files
+    /// whose errors may have changed as a result, which are returned.
+    pub async fn patch_file(&mut self, path: &PathBuf, contents: &String) -> Result<Vec<PathBuf>> {
         self.source_files.insert(path.clone(), contents.clone());
+        self.lookups = Mutex::default();
 
-        let result = self.parse_file(contents);
+        let result = self.parse_file(path, contents);
         let result_schema = result.as_ref().map(|(schema, _)| schema);
 
+        let previous_entities = self.entities_of(path);
+        let previous_imports = self.imports_of(path);
+        let previous_policies = self.policies_of(path);
+
         self.entities.retain(|x| &x.origin != path);
 
         match result_schema {
@@ -163,7 +330,67 @@ impl Workspace {
             }
         };
 
-        Ok(())
+        // Relationships are resolved with the imports of the file declaring
+        // them, so the entity map depends on both
+        let entities_changed = self.entities_of(path) != previous_entities
+            || self.imports_of(path) != previous_imports;
+        if entities_changed {
+            self.analysis.entities = Typechecker::entity_map(&self.entities, &self.files);
+        }
+
+        // What policies use and which fragments recurse only needs walking
+        // again when the entities or the policies of the file changed
+        let typechecker = if entities_changed || self.policies_of(path) != previous_policies {
+            let entity_map = self.analysis.entities.clone();
+            let typechecker = Typechecker::with_entity_map(&self.entities, &self.files, entity_map);
+            self.analysis = typechecker.analysis();
+            typechecker
+        } else {
+            Typechecker::with_analysis(&self.entities, &self.files, &self.analysis)
+        };
+
+        let affected = self.symbols.update(&typechecker, &self.files, path);
+
+        let diagnostics = affected
+            .iter()
+            .filter_map(|origin| self.resolve_schema(origin))
+            .map(|schema| {
+                (
+                    schema.origin.clone(),
+                    self.file_errors(&typechecker, schema),
+                )
+            })
+            .collect::<Vec<_>>();
+        self.diagnostics.extend(diagnostics);
+
+        Ok(affected)
+    }
+
+    fn entities_of(&self, path: &PathBuf) -> Vec<Parsed<Entity>> {
+        self.entities
+            .iter()
+            .filter(|x| &x.origin == path)
+            .map(|x| x.data.clone())
+            .collect()
+    }
+
+    fn imports_of(&self, path: &PathBuf) -> Option<Vec<Parsed<Import>>> {
+        let file = self.resolve_schema(path)?.data.as_ref().ok()?;
+
+        Some(file.imports.clone())
+    }
+
+    /// The fragments and entrypoints of the file at `path`, which policies
+    /// are walked through.
+    fn policies_of(&self, path: &PathBuf) -> Option<Policies> {
+        let file = self.resolve_schema(path)?.data.as_ref().ok()?;
+
+        Some((file.fragments.clone(), file.entrypoints.clone()))
+    }
+
+    /// A typechecker for the whole workspace, reusing its analysis.
+    fn typechecker(&self) -> Typechecker<'_> {
+        Typechecker::with_analysis(&self.entities, &self.files, &self.analysis)
     }
 
     pub fn file_name_iter(&self) -> impl Iterator<Item = &PathBuf> {
@@ -184,8 +411,10 @@ impl Workspace {
             let parsed_contents = ParserStatement::parse(contents);
             match parsed_contents {
                 Err(e) => files.push(WithOrigin::new(Err(RuuLangError::from(e)), origin.clone())),
-                Ok(data) => {
-                    let (schemata, rule) = data.assemble();
+                Ok((data, syntax_errors)) => {
+                    let (schemata, mut rule) = data.assemble();
+                    rule.module = self.module_name(origin);
+                    rule.syntax_errors = syntax_errors;
 
                     entities.extend(
                         schemata
@@ -214,29 +443,207 @@ impl Workspace {
         self.files.iter().find(|x| &x.origin == path)
     }
 
-    pub fn entity_by_name(&self, entity: &String) -> Option<&WithOrigin<Parsed<Entity>>> {
-        self.entities
+    /// Finds the entity `entity` refers to when written in the file at
+    /// `from`.
+    pub fn entity_by_name(
+        &self,
+        from: &PathBuf,
+        entity: &str,
+    ) -> Option<&WithOrigin<Parsed<Entity>>> {
+        let key = (from.clone(), entity.to_string());
+        let mut lookups = self.lookups.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let index = *lookups
+            .entities
+            .entry(key)
+            .or_insert_with(|| self.find_entity(from, entity));
+
+        index.map(|idx| &self.entities[idx])
+    }
+
+    fn find_entity(&self, from: &PathBuf, entity: &str) -> Option<usize> {
+        let modules = ModuleIndex::new(&self.entities, &self.files);
+        let (_, name) = split_reference(entity);
+
+        let declared_in = self
+            .entities
             .iter()
-            .find(|x| &x.data.data.name.data.value == entity)
+            .filter(|x| x.data.data.name.data.value == name)
+            .map(|x| modules.module_of(&x.origin))
+            .collect::<Vec<_>>();
+        let module = modules
+            .resolve(modules.module_of(from), entity, &declared_in)
+            .ok()?;
+
+        self.entities.iter().position(|x| {
+            x.data.data.name.data.value == name && modules.module_of(&x.origin) == module
+        })
     }
 
+    /// Finds the fragment `fragment` refers to when written in the file at
+    /// `from`, for the given entity.
     pub fn fragment_by_name_and_entity(
         &self,
-        fragment: &String,
-        entity: &String,
+        from: &PathBuf,
+        fragment: &str,
+        entity: &WithOrigin<Parsed<Entity>>,
     ) -> Option<&Parsed<Fragment>> {
-        self.files
+        let key = (
+            from.clone(),
+            fragment.to_string(),
+            entity.origin.clone(),
+            entity.data.data.name.data.value.clone(),
+        );
+        let mut lookups = self.lookups.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let index = *lookups
+            .fragments
+            .entry(key)
+            .or_insert_with(|| self.find_fragment(from, fragment, entity));
+
+        index.and_then(|(file, idx)| self.files[file].data.as_ref().ok()?.fragments.get(idx))
+    }
+
+    fn find_fragment(
+        &self,
+        from: &PathBuf,
+        fragment: &str,
+        entity: &WithOrigin<Parsed<Entity>>,
+    ) -> Option<(usize, usize)> {
+        let typechecker = self.typechecker();
+        let modules = typechecker.modules();
+
+        let entity_name = modules.entity_name(
+            modules.module_of(&entity.origin),
+            &entity.data.data.name.data.value,
+        );
+        let found = typechecker
+            .resolve_fragment(modules.module_of(from), fragment, &entity_name)
+            .ok()?
+            .fragment;
+
+        self.files.iter().enumerate().find_map(|(file, schema)| {
+            let fragments = &schema.data.as_ref().ok()?.fragments;
+            let idx = fragments.iter().position(|x| std::ptr::eq(x, found))?;
+
+            Some((file, idx))
+        })
+    }
+
+    /// The name at `offset` in the file at `path` that refers to a
+    /// declaration, if any. Where names are nested, as the grants implied by
+    /// a grant declaration are, the innermost one is returned.
+    pub fn symbol_at(&self, path: &PathBuf, offset: usize) -> Option<SymbolRef> {
+        self.symbols
+            .symbols(path)
             .iter()
-            .flat_map(|x| x.data.iter())
-            .flat_map(|x| &x.fragments)
-            .find(|x| {
-                &x.data.name.data.value == fragment && &x.data.for_entity.data.value == entity
+            .filter(|symbol| symbol.loc.0 <= offset && offset <= symbol.loc.1)
+            .min_by_key(|symbol| symbol.loc.1 - symbol.loc.0)
+            .cloned()
+    }
+
+    /// Every place `symbol` is declared. An entity may be declared in several
+    /// parts, and so may have more than one declaration.
+    pub fn declarations(&self, symbol: &Symbol) -> Vec<WithOrigin<(usize, usize)>> {
+        self.symbols
+            .references(symbol)
+            .into_iter()
+            .filter(|x| x.data.is_declaration)
+            .map(|x| x.as_with_data(x.data.loc))
+            .collect()
+    }
+
+    /// Every name in the workspace that resolves to `symbol`, including its
+    /// declarations.
+    pub fn references(&self, symbol: &Symbol) -> Vec<WithOrigin<SymbolRef>> {
+        self.symbols
+            .references(symbol)
+            .into_iter()
+            .map(|x| x.as_with_data(x.data.clone()))
+            .collect()
+    }
+
+    /// The names that can be written at `offset` in the file at `path`.
+    pub fn completions(&self, path: &PathBuf, offset: usize) -> Vec<Completion> {
+        let (Some(source), Some(Ok(file))) = (
+            self.source_files.get(path),
+            self.resolve_schema(path).map(|x| &x.data),
+        ) else {
+            return vec![];
+        };
+
+        let typechecker = self.typechecker();
+        completions(&typechecker, &self.entities, file, source, offset)
+    }
+
+    /// What renaming the name at `offset` in the file at `path` changes, if
+    /// there is a name there that can be renamed.
+    pub fn prepare_rename(&self, path: &PathBuf, offset: usize) -> Option<RenameTarget> {
+        let source = self.source_files.get(path)?;
+        let reference = self.symbol_at(path, offset)?;
+
+        RenameTarget::new(source, &reference, offset)
+    }
+
+    /// The edits that rename the name at `offset` in the file at `path` to
+    /// `name`, at its declarations and every use that resolves to it.
+    pub fn rename(
+        &self,
+        path: &PathBuf,
+        offset: usize,
+        name: &str,
+    ) -> Result<Vec<WithOrigin<Edit>>> {
+        if !is_valid_name(name) {
+            return Err(RuuLangError::Other("The new name is not a valid name"));
+        }
+
+        let target = self
+            .prepare_rename(path, offset)
+            .ok_or(RuuLangError::Other("There is no name to rename here"))?;
+
+        let edits = self
+            .symbols
+            .find(|symbol| target.renames(symbol))
+            .into_iter()
+            .filter_map(|reference| {
+                let source = self.source_files.get(&reference.origin)?;
+                let edit = target.edit(source, reference.data, name)?;
+
+                Some(reference.as_with_data(edit))
+            })
+            .collect();
+
+        Ok(edits)
+    }
+
+    /// The module declared by the file at `origin`, named after its path from
+    /// the workspace root.
+    pub fn module_name(&self, origin: &Path) -> String {
+        let root = self
+            .config
+            .workspace
+            .root
+            .as_ref()
+            .unwrap_or(&self.working_dir);
+        let relative = origin.strip_prefix(root).unwrap_or(origin);
+
+        relative
+            .with_extension("")
+            .components()
+            .filter_map(|x| match x {
+                Component::Normal(segment) => segment.to_str(),
+                _ => None,
             })
+            .collect::<Vec<_>>()
+            .join("::")
     }
 
-    fn parse_file(&self, contents: &String) -> Result<(RuuLangSchema, RuuLangFile)> {
-        let token_contents = ParserStatement::parse(from_utf8(contents.as_bytes()).unwrap())?;
-        let (schemata, rule) = token_contents.assemble();
+    fn parse_file(&self, path: &Path, contents: &String) -> Result<(RuuLangSchema, RuuLangFile)> {
+        let (token_contents, syntax_errors) =
+            ParserStatement::parse(from_utf8(contents.as_bytes()).unwrap())?;
+        let (schemata, mut rule) = token_contents.assemble();
+        rule.module = self.module_name(path);
+        rule.syntax_errors = syntax_errors;
 
         Ok((schemata, rule))
     }
@@ -290,30 +697,78 @@ impl Workspace {
         result
     }
 
-    async fn compile_one(&self, schema: &WithOrigin<Result<RuuLangFile>>) -> Result<()> {
+    async fn compile_one(
+        &self,
+        typechecker: &Typechecker<'_>,
+        schema: &WithOrigin<Result<RuuLangFile>>,
+    ) -> Result<()> {
+        // Files that failed to parse are reported by the typechecker
+        let original = match &schema.data {
+            Ok(d) => d,
+            Err(_) => return Ok(()),
+        };
+
+        // Don't emit code for a file that was only partially parsed
+        if !original.syntax_errors.is_empty() {
+            return Ok(());
+        }
+
+        let file = emitted_file(typechecker, original);
+        let schema = WithOrigin::new(file, schema.origin.clone());
+
         if self.config.json.as_ref().map_or(false, |x| x.enabled) {
-            self.compile_one_json(schema).await?;
+            self.compile_one_json(&schema).await?;
         }
 
         if self.config.python.as_ref().map_or(false, |x| x.enabled) {
-            self.compile_one_python(schema).await?;
+            let denied_grants = self.denied_grants(typechecker, &schema.origin);
+            self.compile_one_python(&schema, denied_grants).await?;
         }
 
         Ok(())
     }
 
-    async fn compile_one_python(&self, schema: &WithOrigin<Result<RuuLangFile>>) -> Result<()> {
+    /// The grants negated by rules on each relationship declared in
+    /// `origin`, so the emitted rule classes can accept them.
+    fn denied_grants(
+        &self,
+        typechecker: &Typechecker,
+        origin: &PathBuf,
+    ) -> HashMap<(String, String), Vec<Vec<String>>> {
+        let modules = typechecker.modules();
+        let module = modules.module_of(origin);
+        let mut denied_grants = HashMap::new();
+
+        for entity in self.entities.iter().filter(|x| &x.origin == origin) {
+            let name = &entity.data.data.name.data.value;
+            let resolved = modules.entity_name(module, name);
+
+            for relationship in &entity.data.data.relationships {
+                let relationship = &relationship.data.relationship_name.data.value;
+                let denied = typechecker.denied_grants(&resolved, relationship);
+
+                if !denied.is_empty() {
+                    denied_grants.insert((name.clone(), relationship.clone()), denied.to_vec());
+                }
+            }
+        }
+
+        denied_grants
+    }
+
+    async fn compile_one_python(
+        &self,
+        schema: &WithOrigin<RuuLangFile>,
+        denied_grants: HashMap<(String, String), Vec<Vec<String>>>,
+    ) -> Result<()> {
         let mut new_file = schema.origin.clone();
         new_file.set_extension("py");
 
-        let file = match &schema.data {
-            Ok(d) => d,
-            Err(_) => return Ok(()),
-        };
-
+        let file = &schema.data;
         let entities = &self.entities;
 
-        let python = PythonCodegen::new(&schema.origin, &new_file, &self.config, entities, &file);
+        let python = PythonCodegen::new(&schema.origin, &new_file, &self.config, entities, file)
+            .with_denied_grants(denied_grants);
         let result = python.serialize_schema_and_file();
 
         fs::write(new_file, result).await?;
@@ -321,14 +776,298 @@ impl Workspace {
         Ok(())
     }
 
-    async fn compile_one_json(&self, schema: &WithOrigin<Result<RuuLangFile>>) -> Result<()> {
+    async fn compile_one_json(&self, schema: &WithOrigin<RuuLangFile>) -> Result<()> {
         let mut new_file = schema.origin.clone();
         new_file.set_extension("json");
 
-        let data = &schema.data.clone()?;
-        let as_json = serde_json::to_string_pretty(&data).unwrap();
+        let as_json = serde_json::to_string_pretty(&schema.data).unwrap();
         fs::write(new_file, &as_json).await?;
 
         Ok(())
     }
 }
+
+/// A copy of `original` to emit code for, annotated with what downstream
+/// runtimes need to know about its policies.
+fn emitted_file(typechecker: &Typechecker, original: &RuuLangFile) -> RuuLangFile {
+    // Let downstream runtimes know which fragments recurse
+    let mut file = original.clone();
+    for (fragment, original) in file.fragments.iter_mut().zip(&original.fragments) {
+        fragment.data.recursive = typechecker.is_recursive(&file.module, original);
+    }
+
+    // Emit the arguments of `int` parameters as numbers
+    let module = file.module.clone();
+    for fragment in &mut file.fragments {
+        let reference = &fragment.data.for_entity.data.value;
+        if let Ok(entity) = typechecker.resolve_entity(&module, reference) {
+            type_arguments(typechecker, entity, &mut fragment.data.rules);
+        }
+    }
+    for entrypoint in &mut file.entrypoints {
+        let reference = &entrypoint.data.entrypoint.data.value;
+        if let Ok(entity) = typechecker.resolve_entity(&module, reference) {
+            type_arguments(typechecker, entity, &mut entrypoint.data.rules);
+        }
+    }
+
+    file
+}
+
+/// Records the argument types the schema declares on the attributes of
+/// `rules`, which start from `source`, and of the rules nested in them.
+fn type_arguments(typechecker: &Typechecker, source: &TcEntity, rules: &mut [Parsed<Rule>]) {
+    for rule in rules {
+        // Universal rules don't name a relationship
+        let Some(relationship) = source.get_rule(&rule.data.relationship.data.value) else {
+            continue;
+        };
+
+        for attribute in &mut rule.data.attributes {
+            attribute.data.argument_types = relationship
+                .data
+                .attributes
+                .iter()
+                .find(|x| x.data.name.data.value == attribute.data.name.data.value)
+                .and_then(|x| x.data.parameters.clone());
+        }
+
+        if let Some(target) = typechecker.entity(&relationship.data.entity_name.data.value) {
+            type_arguments(typechecker, target, &mut rule.data.rules);
+        }
+    }
+}
+
+/// Fixes that delete everything on a line remove the line itself, so they
+/// don't leave blank lines behind.
+fn remove_whole_lines(source: &str, error: &mut TypecheckError) {
+    let Some(fix) = &mut error.fix else {
+        return;
+    };
+
+    for edit in fix.edits.iter_mut().filter(|x| x.replacement.is_empty()) {
+        edit.loc = whole_line(source, edit.loc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = "
+        entity User {
+            documents -> Document;
+        }
+
+        entity Document {
+            read;
+            write;
+        }
+    ";
+
+    fn codes(errors: &[RuuLangError]) -> Vec<&'static str> {
+        errors.iter().map(|x| x.code()).collect()
+    }
+
+    #[tokio::test]
+    async fn patch_rechecks_files_including_a_changed_fragment() {
+        let root = PathBuf::from("/workspace");
+        let mut workspace = Workspace::new(RuuLangConfig::default(), root.clone());
+        let fragment = root.join("fragment.ruu");
+        let policy = root.join("policy.ruu");
+
+        let reader = format!("{SCHEMA} fragment reader for Document {{ read; }}");
+        workspace.patch_file(&fragment, &reader).await.unwrap();
+
+        let including = "@User { documents { read; #reader; } }".to_string();
+        workspace.patch_file(&policy, &including).await.unwrap();
+        assert!(codes(&workspace.typecheck_file(&policy).await).contains(&"RUU0020"));
+
+        // The policy no longer grants `read` twice once the fragment doesn't
+        let writer = format!("{SCHEMA} fragment reader for Document {{ write; }}");
+        let affected = workspace.patch_file(&fragment, &writer).await.unwrap();
+
+        assert!(affected.contains(&policy));
+        assert!(!codes(&workspace.typecheck_file(&policy).await).contains(&"RUU0020"));
+    }
+
+    #[tokio::test]
+    async fn files_without_modules_share_entities() {
+        let root = PathBuf::from("/workspace");
+        let mut workspace = Workspace::new(RuuLangConfig::default(), root.clone());
+        let first = root.join("first.ruu");
+        let second = root.join("second.ruu");
+        let third = root.join("third.ruu");
+
+        workspace
+            .patch_file(&first, &SCHEMA.to_string())
+            .await
+            .unwrap();
+
+        // Both parts of `User` are one entity, so this traverses `documents`
+        let part = "entity User { read; } @User { documents { read; } }";
+        workspace
+            .patch_file(&second, &part.to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            codes(&workspace.typecheck_file(&second).await),
+            Vec::<&str>::new()
+        );
+
+        // A file importing a module has a `User` of its own
+        let own = "import first; entity User { read; } @User { documents { read; } }";
+        workspace
+            .patch_file(&third, &own.to_string())
+            .await
+            .unwrap();
+        assert!(codes(&workspace.typecheck_file(&third).await).contains(&"RUU0003"));
+        assert_eq!(
+            codes(&workspace.typecheck_file(&second).await),
+            Vec::<&str>::new()
+        );
+
+        let qualified = concat!(
+            "import first; entity User { read; friends -> User; }",
+            "@User { friends { read; } } @first::User { documents { read; } }",
+        );
+        workspace
+            .patch_file(&third, &qualified.to_string())
+            .await
+            .unwrap();
+        assert_eq!(codes(&workspace.typecheck_file(&third).await), ["RUU0028"]);
+    }
+
+    #[tokio::test]
+    async fn duplicates_across_files_point_at_the_original() {
+        let root = PathBuf::from("/workspace");
+        let mut workspace = Workspace::new(RuuLangConfig::default(), root.clone());
+        let first = root.join("first.ruu");
+        let second = root.join("second.ruu");
+
+        let reader = format!("{SCHEMA} fragment reader for Document {{ read; }}");
+        workspace.patch_file(&first, &reader).await.unwrap();
+
+        let conflicting = concat!(
+            "entity User { documents -> User; }",
+            "fragment reader for Document { write; }",
+            "@User { documents { #reader; } }",
+        );
+        workspace
+            .patch_file(&second, &conflicting.to_string())
+            .await
+            .unwrap();
+
+        // The duplicate fragment is also never included
+        let errors = workspace.typecheck_file(&second).await;
+        assert_eq!(codes(&errors), ["RUU0013", "RUU0015", "RUU0017"]);
+
+        for error in &errors[..2] {
+            assert_eq!(error.related()[0].file.as_ref(), Some(&first));
+        }
+        assert_eq!(
+            codes(&workspace.typecheck_file(&first).await),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[tokio::test]
+    async fn fragment_denials_are_checked_and_recorded() {
+        let root = PathBuf::from("/workspace");
+        let mut workspace = Workspace::new(RuuLangConfig::default(), root.clone());
+        let schema = root.join("schema.ruu");
+
+        let useless = format!(
+            "{SCHEMA} fragment useless for Document {{ !write; }} \
+             @User {{ documents {{ read; #useless; }} }}"
+        );
+        workspace.patch_file(&schema, &useless).await.unwrap();
+        assert_eq!(codes(&workspace.typecheck_file(&schema).await), ["RUU0016"]);
+
+        let reader = format!(
+            "{SCHEMA} fragment reader for Document {{ read; !read; }} \
+             @User {{ documents {{ write; #reader; }} }}"
+        );
+        workspace.patch_file(&schema, &reader).await.unwrap();
+        assert_eq!(
+            codes(&workspace.typecheck_file(&schema).await),
+            Vec::<&str>::new()
+        );
+
+        let typechecker = workspace.typechecker();
+        assert_eq!(
+            typechecker.denied_grants("User", "documents"),
+            [vec!["read".to_string()]]
+        );
+    }
+
+    #[tokio::test]
+    async fn lookups_follow_edits() {
+        let root = PathBuf::from("/workspace");
+        let mut workspace = Workspace::new(RuuLangConfig::default(), root.clone());
+        let schema = root.join("schema.ruu");
+
+        let reader = format!("{SCHEMA} fragment reader for Document {{ read; }}");
+        workspace.patch_file(&schema, &reader).await.unwrap();
+
+        let document = workspace
+            .entity_by_name(&schema, "Document")
+            .unwrap()
+            .clone();
+        let found = workspace.fragment_by_name_and_entity(&schema, "reader", &document);
+        assert_eq!(found.unwrap().data.name.data.value, "reader");
+
+        let writer = format!("{SCHEMA} fragment writer for Document {{ write; }}");
+        workspace.patch_file(&schema, &writer).await.unwrap();
+
+        assert!(workspace
+            .fragment_by_name_and_entity(&schema, "reader", &document)
+            .is_none());
+        assert!(workspace
+            .fragment_by_name_and_entity(&schema, "writer", &document)
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn int_arguments_are_emitted_as_numbers() {
+        let root = PathBuf::from("/workspace");
+        let mut workspace = Workspace::new(RuuLangConfig::default(), root.clone());
+        let schema = root.join("schema.ruu");
+
+        let source = concat!(
+            "entity User { documents :level(int, string) :role(admin) -> Document; }",
+            "entity Document { read; }",
+            "@User { documents :level(-3, 4) :role(admin) { read; } }",
+        );
+        workspace
+            .patch_file(&schema, &source.to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            codes(&workspace.typecheck_file(&schema).await),
+            Vec::<&str>::new()
+        );
+
+        let typechecker = workspace.typechecker();
+        let file = workspace.resolve_schema(&schema).unwrap();
+        let file = emitted_file(&typechecker, file.data.as_ref().unwrap());
+        let json = serde_json::to_value(&file).unwrap();
+
+        // Schemas keep listing their parameters as arguments
+        let declared = &json["entities"][0]["relationships"][0]["attributes"];
+        assert_eq!(
+            declared[0]["arguments"],
+            serde_json::json!(["int", "string"])
+        );
+        assert_eq!(declared[1]["arguments"], serde_json::json!(["admin"]));
+        assert_eq!(
+            declared[1]["parameters"],
+            serde_json::json!([{"one_of": ["admin"]}])
+        );
+
+        let used = &json["entrypoints"][0]["rules"][0]["attributes"];
+        assert_eq!(used[0]["arguments"], serde_json::json!([-3, "4"]));
+        assert_eq!(used[1]["arguments"], serde_json::json!(["admin"]));
+        assert!(used[0].get("parameters").is_none());
+    }
+}
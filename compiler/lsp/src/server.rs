@@ -1,3 +1,5 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use ruulang_core::{
     config::config::RuuLangConfig,
     parser::{
@@ -7,22 +9,33 @@ use ruulang_core::{
         ruulang_ast::{Attribute, Fragment, Grant},
         schema_ast::{Entity, Relationship},
     },
-    utils::error::{RuuLangError, TypecheckError},
-    workspace::workspace::Workspace,
+    utils::{
+        error::{RuuLangError, Severity},
+        with_origin::WithOrigin,
+    },
+    workspace::{completion::CompletionKind, symbols::SymbolRef, workspace::Workspace},
 };
 use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
 use tower_lsp::{
     jsonrpc,
     lsp_types::{
-        Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
-        Hover, HoverContents, HoverParams, HoverProviderCapability, InitializeParams,
-        InitializeResult, InitializedParams, MarkupContent, MarkupKind, MessageType,
-        ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+        request::{GotoDeclarationParams, GotoDeclarationResponse},
+        CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+        CodeActionProviderCapability, CodeActionResponse, CompletionItem, CompletionItemKind,
+        CompletionOptions, CompletionParams, CompletionResponse, DeclarationCapability, Diagnostic,
+        DiagnosticRelatedInformation, DiagnosticSeverity, DidChangeTextDocumentParams,
+        DidOpenTextDocumentParams, DocumentHighlight, DocumentHighlightKind,
+        DocumentHighlightParams, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+        HoverContents, HoverParams, HoverProviderCapability, InitializeParams, InitializeResult,
+        InitializedParams, Location, MarkupContent, MarkupKind, MessageType, NumberOrString, OneOf,
+        PrepareRenameResponse, ReferenceParams, RenameOptions, RenameParams, ServerCapabilities,
+        TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit,
+        Url, WorkspaceEdit,
     },
     Client, LanguageServer,
 };
 
-use crate::utils::{get_line_prefix_sum, location_pair_to_range, position_to_location};
+use crate::utils::{apply_change, location_pair_to_range, position_to_location};
 
 pub struct RuuLangServer {
     client: Client,
@@ -38,39 +51,59 @@ impl RuuLangServer {
         }
     }
 
-    async fn show_diagnostics(&self, uri: Url, contents: &String, errors: &Vec<RuuLangError>) {
+    async fn show_diagnostics(
+        &self,
+        workspace: &Workspace,
+        uri: Url,
+        contents: &String,
+        errors: &Vec<RuuLangError>,
+    ) {
         let mut diagnostics = vec![];
 
         for error in errors {
-            match error {
-                RuuLangError::TypecheckError(TypecheckError::GeneralError(data)) => {
-                    if data.loc.is_none() {
-                        continue;
-                    }
-
-                    let loc = data.loc.unwrap();
-
-                    diagnostics.push(Diagnostic::new(
-                        location_pair_to_range(&contents, loc.0 as u32, loc.1 as u32),
-                        Some(DiagnosticSeverity::ERROR),
-                        None,
-                        None,
-                        data.data.clone(),
-                        None,
-                        None,
-                    ))
-                }
-                RuuLangError::RuuLangParseError(location) => diagnostics.push(Diagnostic::new(
-                    location_pair_to_range(&contents, *location as u32, *location as u32 + 1),
-                    Some(DiagnosticSeverity::ERROR),
-                    None,
-                    None,
-                    "Parse error".to_string(),
-                    None,
-                    None,
-                )),
-                _ => {}
-            }
+            let Some(loc) = error.loc() else {
+                continue;
+            };
+
+            let severity = match error.severity() {
+                Severity::Error => DiagnosticSeverity::ERROR,
+                Severity::Warning => DiagnosticSeverity::WARNING,
+                Severity::Info => DiagnosticSeverity::INFORMATION,
+            };
+
+            // Related definitions may be declared in other files
+            let related_information = error
+                .related()
+                .iter()
+                .filter_map(|related| {
+                    let loc = related.loc?;
+                    let (uri, contents) = match &related.file {
+                        Some(file) => (
+                            Url::from_file_path(file).ok()?,
+                            workspace.resolve_file(file)?,
+                        ),
+                        None => (uri.clone(), contents),
+                    };
+
+                    Some(DiagnosticRelatedInformation {
+                        location: Location::new(
+                            uri,
+                            location_pair_to_range(contents, loc.0 as u32, loc.1 as u32),
+                        ),
+                        message: related.message.clone(),
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            diagnostics.push(Diagnostic::new(
+                location_pair_to_range(contents, loc.0 as u32, loc.1 as u32),
+                Some(severity),
+                Some(NumberOrString::String(error.code().to_string())),
+                None,
+                error.to_string(),
+                (!related_information.is_empty()).then_some(related_information),
+                None,
+            ));
         }
 
         self.client
@@ -118,19 +151,29 @@ impl RuuLangServer {
 
             let success = workspace.patch_file(&file_name, contents).await;
             match success {
-                Ok(_) => {
-                    let contents = &contents;
-                    let errors = workspace.typecheck_file(&file_name).await;
+                // The edit may change the errors of other files that depend
+                // on it, so theirs are published again too
+                Ok(affected) => {
+                    for file_name in affected {
+                        let (Some(contents), Ok(uri)) = (
+                            workspace.resolve_file(&file_name),
+                            Url::from_file_path(&file_name),
+                        ) else {
+                            continue;
+                        };
 
-                    self.client
-                        .log_message(
-                            MessageType::INFO,
-                            format!("Typechecking {:#?}: {:#?}", &file_name, &errors),
-                        )
-                        .await;
+                        let errors = workspace.typecheck_file(&file_name).await;
 
-                    self.show_diagnostics(file_uri.clone(), &contents, &errors)
-                        .await;
+                        self.client
+                            .log_message(
+                                MessageType::INFO,
+                                format!("Typechecking {:#?}: {:#?}", &file_name, &errors),
+                            )
+                            .await;
+
+                        self.show_diagnostics(&workspace, uri, contents, &errors)
+                            .await;
+                    }
                 }
                 Err(err) => {
                     self.client
@@ -141,6 +184,62 @@ impl RuuLangServer {
         }
     }
 
+    /// The file and offset of the cursor.
+    fn offset_at(
+        &self,
+        workspace: &Workspace,
+        position: &TextDocumentPositionParams,
+    ) -> Option<(PathBuf, usize)> {
+        let file_name = position.text_document.uri.to_file_path().ok()?;
+        let file_contents = workspace.resolve_file(&file_name)?;
+
+        let location = position_to_location(file_contents, &position.position) as usize;
+
+        Some((file_name, location))
+    }
+
+    /// The name under the cursor, if it refers to a declaration.
+    fn symbol_at(
+        &self,
+        workspace: &Workspace,
+        position: &TextDocumentPositionParams,
+    ) -> Option<SymbolRef> {
+        let (file_name, location) = self.offset_at(workspace, position)?;
+
+        workspace.symbol_at(&file_name, location)
+    }
+
+    /// Where a span of one of the workspace's files is, for the client.
+    fn location(
+        &self,
+        workspace: &Workspace,
+        span: &WithOrigin<(usize, usize)>,
+    ) -> Option<Location> {
+        let contents = workspace.resolve_file(&span.origin)?;
+        let uri = Url::from_file_path(&span.origin).ok()?;
+        let (start, end) = span.data;
+
+        Some(Location::new(
+            uri,
+            location_pair_to_range(contents, start as u32, end as u32),
+        ))
+    }
+
+    /// The declarations of the name under the cursor. Each declaration is
+    /// looked up across the workspace, so it may be in another file.
+    async fn definitions(&self, position: TextDocumentPositionParams) -> Option<Vec<Location>> {
+        let workspace = self.workspace_for_file(&position.text_document.uri).await?;
+        let symbol = self.symbol_at(&workspace, &position)?;
+
+        let locations = workspace
+            .declarations(&symbol.symbol)
+            .iter()
+            .filter_map(|declaration| self.location(&workspace, declaration))
+            .collect::<Vec<_>>();
+
+        (!locations.is_empty()).then_some(locations)
+    }
+
     fn serialize_entity(&self, entity: &Parsed<Entity>) -> String {
         let mut result = String::new();
         result.push_str(format!("```ruulang\nentity {}\n```\n\n", entity.data.name).as_str());
@@ -217,10 +316,22 @@ impl RuuLangServer {
     ) -> String {
         let mut result = String::new();
 
+        let parameters = match &attribute.data.parameters {
+            Some(parameters) => format!(
+                "({})",
+                parameters
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            None => String::new(),
+        };
+
         result.push_str(
             format!(
-                "```ruulang\n {}:{}\n```\n\n",
-                &rule.data.relationship_name, &attribute.data.name
+                "```ruulang\n {}:{}{}\n```\n\n",
+                &rule.data.relationship_name, &attribute.data.name, parameters
             )
             .as_str(),
         );
@@ -263,8 +374,25 @@ impl LanguageServer for RuuLangServer {
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                declaration_provider: Some(DeclarationCapability::Simple(true)),
+                references_provider: Some(OneOf::Left(true)),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                completion_provider: Some(CompletionOptions {
+                    trigger_characters: Some(vec![
+                        ":".to_string(),
+                        "@".to_string(),
+                        "#".to_string(),
+                    ]),
+                    ..Default::default()
+                }),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                    TextDocumentSyncKind::INCREMENTAL,
                 )),
 
                 ..Default::default()
@@ -293,9 +421,24 @@ impl LanguageServer for RuuLangServer {
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let file_uri = &params.text_document.uri;
-        let contents = &params.content_changes[0].text;
+        let Ok(file_name) = file_uri.to_file_path() else {
+            return;
+        };
+
+        // Changes are ranges of the file as last seen, applied in order
+        let mut contents = match self.workspace_for_file(file_uri).await {
+            Some(workspace) => workspace
+                .resolve_file(&file_name)
+                .cloned()
+                .unwrap_or_default(),
+            None => return,
+        };
+
+        for change in params.content_changes {
+            apply_change(&mut contents, change);
+        }
 
-        self.patch_and_notify(file_uri, contents, false).await;
+        self.patch_and_notify(file_uri, &contents, false).await;
     }
 
     async fn hover(&self, params: HoverParams) -> jsonrpc::Result<Option<Hover>> {
@@ -324,14 +467,14 @@ impl LanguageServer for RuuLangServer {
         };
 
         let location = position_to_location(
-            &get_line_prefix_sum(file_contents),
+            file_contents,
             &params.text_document_position_params.position,
         ) as usize;
 
         let descension = schema.descend_at((location, location));
 
         if let Some(stack) = descension {
-            let mut entities: Vec<&Entity> = vec![];
+            let mut entities: Vec<&WithOrigin<Parsed<Entity>>> = vec![];
             let mut rels: Vec<&Relationship> = vec![];
             let mut final_result: Option<String> = None;
 
@@ -350,11 +493,12 @@ impl LanguageServer for RuuLangServer {
                         },
                     ) => {
                         let entity_name = &entrypoint.entrypoint;
-                        let Some(found_entity) = workspace.entity_by_name(&entity_name) else {
+                        let Some(found_entity) = workspace.entity_by_name(&file_name, entity_name)
+                        else {
                             continue;
                         };
 
-                        entities.push(&found_entity.data.data);
+                        entities.push(found_entity);
                     }
 
                     (
@@ -365,7 +509,12 @@ impl LanguageServer for RuuLangServer {
                             ..
                         },
                     ) => {
-                        entities.push(entity);
+                        let Some(found_entity) = workspace.entity_by_name(&file_name, &entity.name)
+                        else {
+                            continue;
+                        };
+
+                        entities.push(found_entity);
                     }
 
                     (
@@ -378,11 +527,12 @@ impl LanguageServer for RuuLangServer {
                     ) => {
                         let entity_name = &found_fragment.for_entity.data;
 
-                        let Some(found_entity) = workspace.entity_by_name(&entity_name) else {
+                        let Some(found_entity) = workspace.entity_by_name(&file_name, entity_name)
+                        else {
                             continue;
                         };
 
-                        entities.push(&found_entity.data.data);
+                        entities.push(found_entity);
                     }
 
                     (
@@ -394,6 +544,8 @@ impl LanguageServer for RuuLangServer {
                         },
                     ) => {
                         let Some(relationship_object) = entity
+                            .data
+                            .data
                             .relationships
                             .iter()
                             .find(|x| x.data.relationship_name.data == rule.relationship.data)
@@ -401,13 +553,13 @@ impl LanguageServer for RuuLangServer {
                             continue;
                         };
 
-                        let Some(next_entity) =
-                            workspace.entity_by_name(&relationship_object.data.entity_name)
+                        let Some(next_entity) = workspace
+                            .entity_by_name(&entity.origin, &relationship_object.data.entity_name)
                         else {
                             continue;
                         };
 
-                        entities.push(&next_entity.data.data);
+                        entities.push(next_entity);
                         rels.push(&relationship_object);
                     }
 
@@ -419,21 +571,21 @@ impl LanguageServer for RuuLangServer {
                             ..
                         },
                     ) => {
-                        let Some(relationship_object) = entity
-                            .relationships
-                            .iter()
-                            .find(|x| x.data.relationship_name.data == rel.relationship_name.data)
+                        let Some(relationship_object) =
+                            entity.data.data.relationships.iter().find(|x| {
+                                x.data.relationship_name.data == rel.relationship_name.data
+                            })
                         else {
                             continue;
                         };
 
-                        let Some(next_entity) =
-                            workspace.entity_by_name(&relationship_object.data.entity_name)
+                        let Some(next_entity) = workspace
+                            .entity_by_name(&entity.origin, &relationship_object.data.entity_name)
                         else {
                             continue;
                         };
 
-                        entities.push(&next_entity.data.data);
+                        entities.push(next_entity);
                         rels.push(&relationship_object);
                     }
 
@@ -445,7 +597,7 @@ impl LanguageServer for RuuLangServer {
                             ..
                         },
                     ) => {
-                        let parent_entity = entities[entities.len() - 2];
+                        let parent_entity = &entities[entities.len() - 2].data.data;
 
                         let Some(relationship_object) = parent_entity
                             .relationships
@@ -475,15 +627,17 @@ impl LanguageServer for RuuLangServer {
                             ..
                         },
                     ) => {
-                        let Some(found_entity) = workspace.entity_by_name(&entity.name) else {
-                            continue;
-                        };
-
-                        let Some(found_grant) = entity.grants.iter().find(|x| x.data == **g) else {
+                        let Some(found_grant) = entity
+                            .data
+                            .data
+                            .grants
+                            .iter()
+                            .find(|x| x.data.grant == g.grant)
+                        else {
                             continue;
                         };
 
-                        final_result = Some(self.serialize_grant(&found_entity.data, &found_grant));
+                        final_result = Some(self.serialize_grant(&entity.data, found_grant));
                     }
 
                     (
@@ -498,28 +652,22 @@ impl LanguageServer for RuuLangServer {
                             value: fragment,
                             kind: IdentifierKind::Fragment,
                         } => {
-                            let entity_name = &entity.name;
-                            let fragment_name = &fragment;
-
-                            let Some(found_entity) = workspace.entity_by_name(&entity_name) else {
-                                continue;
-                            };
-
                             let Some(fragment) =
-                                workspace.fragment_by_name_and_entity(&fragment_name, &entity_name)
+                                workspace.fragment_by_name_and_entity(&file_name, fragment, entity)
                             else {
                                 continue;
                             };
 
-                            final_result =
-                                Some(self.serialize_fragment(&found_entity.data, &fragment));
+                            final_result = Some(self.serialize_fragment(&entity.data, fragment));
                         }
 
                         Identifier {
                             value: entity_name,
                             kind: IdentifierKind::Entity,
                         } => {
-                            let Some(found_entity) = workspace.entity_by_name(&entity_name) else {
+                            let Some(found_entity) =
+                                workspace.entity_by_name(&file_name, entity_name)
+                            else {
                                 continue;
                             };
 
@@ -532,28 +680,23 @@ impl LanguageServer for RuuLangServer {
                         } => {
                             let preceding_entity = entities[entities.len() - 2];
 
-                            let Some(from_entity) =
-                                workspace.entity_by_name(&preceding_entity.name)
-                            else {
-                                continue;
-                            };
-
                             let Some(relationship_object) =
-                                preceding_entity.relationships.iter().find(|x| {
+                                preceding_entity.data.data.relationships.iter().find(|x| {
                                     &x.data.relationship_name.data.value == relationship_name
                                 })
                             else {
                                 continue;
                             };
 
-                            let Some(next_entity) =
-                                workspace.entity_by_name(&relationship_object.data.entity_name)
-                            else {
+                            let Some(next_entity) = workspace.entity_by_name(
+                                &preceding_entity.origin,
+                                &relationship_object.data.entity_name,
+                            ) else {
                                 continue;
                             };
 
                             final_result = Some(self.serialize_relationship(
-                                &from_entity.data,
+                                &preceding_entity.data,
                                 &relationship_object,
                                 &next_entity.data,
                             ));
@@ -580,6 +723,232 @@ impl LanguageServer for RuuLangServer {
         Ok(None)
     }
 
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> jsonrpc::Result<Option<GotoDefinitionResponse>> {
+        let locations = self.definitions(params.text_document_position_params).await;
+
+        Ok(locations.map(GotoDefinitionResponse::Array))
+    }
+
+    // Declarations and definitions are the same thing in a schema
+    async fn goto_declaration(
+        &self,
+        params: GotoDeclarationParams,
+    ) -> jsonrpc::Result<Option<GotoDeclarationResponse>> {
+        let locations = self.definitions(params.text_document_position_params).await;
+
+        Ok(locations.map(GotoDeclarationResponse::Array))
+    }
+
+    async fn references(&self, params: ReferenceParams) -> jsonrpc::Result<Option<Vec<Location>>> {
+        let position = params.text_document_position;
+        let Some(workspace) = self.workspace_for_file(&position.text_document.uri).await else {
+            return Ok(None);
+        };
+
+        let Some(symbol) = self.symbol_at(&workspace, &position) else {
+            return Ok(None);
+        };
+
+        let locations = workspace
+            .references(&symbol.symbol)
+            .iter()
+            .filter(|x| params.context.include_declaration || !x.data.is_declaration)
+            .filter_map(|x| self.location(&workspace, &x.as_with_data(x.data.loc)))
+            .collect();
+
+        Ok(Some(locations))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> jsonrpc::Result<Option<Vec<DocumentHighlight>>> {
+        let position = params.text_document_position_params;
+        let Ok(file_name) = position.text_document.uri.to_file_path() else {
+            return Ok(None);
+        };
+
+        let Some(workspace) = self.workspace_for_file(&position.text_document.uri).await else {
+            return Ok(None);
+        };
+
+        let (Some(symbol), Some(contents)) = (
+            self.symbol_at(&workspace, &position),
+            workspace.resolve_file(&file_name),
+        ) else {
+            return Ok(None);
+        };
+
+        let highlights = workspace
+            .references(&symbol.symbol)
+            .iter()
+            .filter(|x| x.origin == file_name)
+            .map(|x| DocumentHighlight {
+                range: location_pair_to_range(contents, x.data.loc.0 as u32, x.data.loc.1 as u32),
+                kind: Some(match x.data.is_declaration {
+                    true => DocumentHighlightKind::TEXT,
+                    false => DocumentHighlightKind::READ,
+                }),
+            })
+            .collect();
+
+        Ok(Some(highlights))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> jsonrpc::Result<Option<PrepareRenameResponse>> {
+        let Some(workspace) = self.workspace_for_file(&params.text_document.uri).await else {
+            return Ok(None);
+        };
+
+        let Some((file_name, location)) = self.offset_at(&workspace, &params) else {
+            return Ok(None);
+        };
+
+        let (Some(target), Some(contents)) = (
+            workspace.prepare_rename(&file_name, location),
+            workspace.resolve_file(&file_name),
+        ) else {
+            return Ok(None);
+        };
+
+        let (start, end) = target.loc;
+        Ok(Some(PrepareRenameResponse::Range(location_pair_to_range(
+            contents,
+            start as u32,
+            end as u32,
+        ))))
+    }
+
+    async fn rename(&self, params: RenameParams) -> jsonrpc::Result<Option<WorkspaceEdit>> {
+        let position = params.text_document_position;
+        let Some(workspace) = self.workspace_for_file(&position.text_document.uri).await else {
+            return Ok(None);
+        };
+
+        let Some((file_name, location)) = self.offset_at(&workspace, &position) else {
+            return Ok(None);
+        };
+
+        let edits = workspace
+            .rename(&file_name, location, &params.new_name)
+            .map_err(|err| jsonrpc::Error::invalid_params(err.to_string()))?;
+
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        for edit in edits {
+            let (Some(contents), Ok(uri)) = (
+                workspace.resolve_file(&edit.origin),
+                Url::from_file_path(&edit.origin),
+            ) else {
+                continue;
+            };
+
+            let (start, end) = edit.data.loc;
+            changes.entry(uri).or_default().push(TextEdit::new(
+                location_pair_to_range(contents, start as u32, end as u32),
+                edit.data.replacement,
+            ));
+        }
+
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+
+    async fn completion(
+        &self,
+        params: CompletionParams,
+    ) -> jsonrpc::Result<Option<CompletionResponse>> {
+        let position = params.text_document_position;
+        let Some(workspace) = self.workspace_for_file(&position.text_document.uri).await else {
+            return Ok(None);
+        };
+
+        let Some((file_name, location)) = self.offset_at(&workspace, &position) else {
+            return Ok(None);
+        };
+
+        let items = workspace
+            .completions(&file_name, location)
+            .into_iter()
+            .map(|completion| CompletionItem {
+                kind: Some(match completion.kind {
+                    CompletionKind::Entity => CompletionItemKind::CLASS,
+                    CompletionKind::Relationship => CompletionItemKind::FIELD,
+                    CompletionKind::Attribute => CompletionItemKind::PROPERTY,
+                    CompletionKind::Grant => CompletionItemKind::CONSTANT,
+                    CompletionKind::Fragment => CompletionItemKind::MODULE,
+                }),
+                label: completion.label,
+                detail: completion.detail,
+                insert_text: completion.insert_text,
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> jsonrpc::Result<Option<CodeActionResponse>> {
+        let file_uri = params.text_document.uri;
+        let Ok(file_name) = file_uri.to_file_path() else {
+            return Ok(None);
+        };
+
+        let Some(workspace) = self.workspace_for_file(&file_uri).await else {
+            return Ok(None);
+        };
+
+        let Some(contents) = workspace.resolve_file(&file_name) else {
+            return Ok(None);
+        };
+
+        let start = position_to_location(contents, &params.range.start) as usize;
+        let end = position_to_location(contents, &params.range.end) as usize;
+
+        let mut actions = vec![];
+        for error in workspace.typecheck_file(&file_name).await {
+            let (Some(fix), Some(loc)) = (error.fix(), error.loc()) else {
+                continue;
+            };
+
+            // Only offer fixes for errors in the requested range
+            if loc.1 < start || loc.0 > end {
+                continue;
+            }
+
+            let edits = fix
+                .edits
+                .iter()
+                .map(|edit| {
+                    TextEdit::new(
+                        location_pair_to_range(contents, edit.loc.0 as u32, edit.loc.1 as u32),
+                        edit.replacement.clone(),
+                    )
+                })
+                .collect();
+
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: fix.message.clone(),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(WorkspaceEdit::new(HashMap::from([(
+                    file_uri.clone(),
+                    edits,
+                )]))),
+                is_preferred: Some(true),
+                ..Default::default()
+            }));
+        }
+
+        Ok(Some(actions))
+    }
+
     async fn shutdown(&self) -> jsonrpc::Result<()> {
         Ok(())
     }
@@ -1,6 +1,9 @@
-use tower_lsp::lsp_types::{Position, Range};
+use tower_lsp::lsp_types::{Position, Range, TextDocumentContentChangeEvent};
 
-pub fn get_line_prefix_sum(contents: &String) -> Vec<u32> {
+// Positions count UTF-16 code units along a line, as clients do by default,
+// while locations are byte offsets into the file.
+
+fn get_line_prefix_sum(contents: &str) -> Vec<u32> {
     contents
         .split("\n")
         .fold((0, Vec::new()), |(mut sum, mut acc), line| {
@@ -11,27 +14,65 @@ pub fn get_line_prefix_sum(contents: &String) -> Vec<u32> {
         .1
 }
 
-fn location_to_position(line_prefix_sum: &Vec<u32>, location: u32) -> Position {
+fn location_to_position(contents: &str, line_prefix_sum: &[u32], location: u32) -> Position {
     let line = line_prefix_sum
         .iter()
         .enumerate()
         .find(|(_, sum)| **sum > location)
         .map_or(line_prefix_sum.len() - 1, |idx| idx.0 - 1);
 
-    let column = location - line_prefix_sum[line];
+    let start = line_prefix_sum[line] as usize;
+    let end = (location as usize).min(contents.len());
+    let column = contents[start..end]
+        .chars()
+        .map(|c| c.len_utf16() as u32)
+        .sum();
 
     Position::new(line as u32, column)
 }
 
-pub fn position_to_location(line_prefix_sum: &Vec<u32>, position: &Position) -> u32 {
-    line_prefix_sum[position.line as usize] + position.character
+pub fn position_to_location(contents: &str, position: &Position) -> u32 {
+    let line_prefix_sum = get_line_prefix_sum(contents);
+    let Some(&start) = line_prefix_sum.get(position.line as usize) else {
+        return contents.len() as u32;
+    };
+
+    let line = contents[start as usize..]
+        .split('\n')
+        .next()
+        .unwrap_or_default();
+    let mut column = 0;
+
+    for (offset, c) in line.char_indices() {
+        if column >= position.character {
+            return start + offset as u32;
+        }
+
+        column += c.len_utf16() as u32;
+    }
+
+    start + line.len() as u32
 }
 
-pub fn location_pair_to_range(contents: &String, start: u32, end: u32) -> Range {
+pub fn location_pair_to_range(contents: &str, start: u32, end: u32) -> Range {
     let line_prefix_sum = get_line_prefix_sum(contents);
 
-    let start = location_to_position(&line_prefix_sum, start);
-    let end = location_to_position(&line_prefix_sum, end);
+    let start = location_to_position(contents, &line_prefix_sum, start);
+    let end = location_to_position(contents, &line_prefix_sum, end);
 
     Range::new(start, end)
 }
+
+/// Applies a change sent by the client to the contents of a file. A change
+/// without a range replaces the whole file.
+pub fn apply_change(contents: &mut String, change: TextDocumentContentChangeEvent) {
+    let Some(range) = change.range else {
+        *contents = change.text;
+        return;
+    };
+
+    let start = position_to_location(contents, &range.start) as usize;
+    let end = position_to_location(contents, &range.end) as usize;
+
+    contents.replace_range(start..end.max(start), &change.text);
+}
@@ -1,11 +1,19 @@
 use std::env;
 use std::path::PathBuf;
+use std::process::exit;
 use std::sync::mpsc::channel;
 use std::time::Duration;
 
 use clap::{value_parser, Arg, ArgAction, Command};
 use notify::{DebouncedEvent, RecursiveMode, Watcher};
-use ruulang_core::{config::config::RuuLangConfig, workspace::workspace::Workspace};
+use ruulang_core::{
+    config::config::RuuLangConfig,
+    diagnostics::{
+        emitter::MessageFormat,
+        renderer::{ColorChoice, DiagnosticRenderer, RenderStyle},
+    },
+    workspace::workspace::Workspace,
+};
 use tokio::fs;
 
 #[derive(Debug)]
@@ -15,6 +23,7 @@ struct CliOptions {
     pub no_check: bool,
     pub no_emit: bool,
     pub verbose: bool,
+    pub message_format: MessageFormat,
 }
 
 fn get_args() -> CliOptions {
@@ -56,6 +65,30 @@ fn get_args() -> CliOptions {
                 .required(false)
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .required(false)
+                .num_args(1)
+                .action(ArgAction::Set)
+                .value_parser(["auto", "always", "never"])
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::new("plain")
+                .long("plain")
+                .required(false)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("message-format")
+                .long("message-format")
+                .required(false)
+                .num_args(1)
+                .action(ArgAction::Set)
+                .value_parser(["human", "json", "sarif"])
+                .default_value("human"),
+        )
         .get_matches();
 
     let config_path = matches.get_one::<String>("config").map(|x| x.to_owned());
@@ -63,6 +96,24 @@ fn get_args() -> CliOptions {
     let no_check = matches.get_one::<bool>("no-check").unwrap_or(&false);
     let no_emit = matches.get_one::<bool>("no-emit").unwrap_or(&false);
     let verbose = matches.get_one::<bool>("verbose").unwrap_or(&false);
+    let plain = matches.get_one::<bool>("plain").unwrap_or(&false);
+    let color = match matches.get_one::<String>("color").map(|x| x.as_str()) {
+        Some("always") => ColorChoice::Always,
+        Some("never") => ColorChoice::Never,
+        _ => ColorChoice::Auto,
+    };
+    let style = match plain {
+        true => RenderStyle::Plain,
+        false => RenderStyle::Rich,
+    };
+    let message_format = match matches
+        .get_one::<String>("message-format")
+        .map(|x| x.as_str())
+    {
+        Some("json") => MessageFormat::Json,
+        Some("sarif") => MessageFormat::Sarif,
+        _ => MessageFormat::Human(DiagnosticRenderer::new(color, style)),
+    };
 
     CliOptions {
         config_path,
@@ -70,20 +121,31 @@ fn get_args() -> CliOptions {
         no_check: no_check.clone(),
         no_emit: no_emit.clone(),
         verbose: verbose.clone(),
+        message_format,
     }
 }
 
-async fn compile_all(workspace: &mut Workspace, options: &CliOptions) {
+/// Returns the number of errors found
+async fn compile_all(workspace: &mut Workspace, options: &CliOptions) -> usize {
     workspace.reload().await;
 
+    let mut errors = 0;
     if !options.no_check {
-        workspace.typecheck().await.unwrap();
+        errors = workspace.typecheck(&options.message_format).await.unwrap();
     }
 
     if !options.no_emit {
         workspace.compile_all().await.unwrap();
-        println!("Finished compiling!");
+
+        // Keep stdout parseable for the machine-readable formats
+        if options.message_format.is_human() {
+            println!("Finished compiling!");
+        } else {
+            eprintln!("Finished compiling!");
+        }
     }
+
+    errors
 }
 
 async fn compile_on_change(workspace: &mut Workspace, options: &CliOptions) {
@@ -150,9 +212,11 @@ async fn main() {
 
     let mut workspace = Workspace::new(config, working_dir);
 
-    compile_all(&mut workspace, &args).await;
+    let errors = compile_all(&mut workspace, &args).await;
 
     if args.watch {
         compile_on_change(&mut workspace, &args).await;
+    } else if errors > 0 {
+        exit(1);
     }
 }
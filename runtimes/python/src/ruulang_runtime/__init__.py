@@ -1,9 +1,11 @@
-from .registry import (Attribute, Entrypoint, Fragment, Registry, Rule, Schema,
-                       Universal, registry)
+from .registry import (Attribute, Condition, Deny, Entrypoint, Fragment,
+                       Registry, Rule, Schema, Universal, registry)
 
 __all__ = [
     "Universal",
     "Attribute",
+    "Deny",
+    "Condition",
     "Rule",
     "Fragment",
     "Entrypoint",
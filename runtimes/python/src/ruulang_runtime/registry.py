@@ -1,16 +1,23 @@
 # pyright: reportPrivateUsage=false, reportIncompatibleVariableOverride=false
 
+import operator
 from dataclasses import dataclass
 from dataclasses import field as dc_field
-from typing import Any, Callable, Generator, Literal, TypeVar, cast
+from typing import (Any, Callable, Generator, Generic, Literal, Mapping,
+                    TypeVar, cast)
 
-from pydantic import BaseModel
+from pydantic import BaseModel, ConfigDict
 from pydantic import Field as pyd_field
 
 Permission = tuple[str, ...]
 
+_P = TypeVar("_P", bound=Permission)
+
 _OnRegister = cast(Any, ...)
 
+# What universal rules grant on entities that don't declare it
+_DEFAULT_UNIVERSAL: tuple[Permission, ...] = (("read",), ("write",))
+
 
 class RegistryModel(BaseModel):
     # This is a class parameter, not an instance parameter.
@@ -34,21 +41,79 @@ class RegistryModel(BaseModel):
 
 class Attribute(RegistryModel):
     name: str
-    arguments: tuple[str, ...] = pyd_field(default_factory=tuple)
+    arguments: tuple[str | int, ...] = pyd_field(default_factory=tuple)
 
     _entity: str = _OnRegister
     _relationship: str = _OnRegister
     _attribute: str = _OnRegister
 
 
+class Deny(BaseModel, Generic[_P]):
+    """
+    A negated grant. It withholds the permission, and everything it implies,
+    from the other grants of the same rule, but never from other rules.
+    """
+
+    model_config = ConfigDict(frozen=True)
+
+    deny: _P
+
+
+def _covers(grants: set[Permission], permission: Permission) -> bool:
+    return any(permission[: len(grant)] == grant for grant in grants)
+
+
+_OPERATORS: dict[str, Callable[[Any, Any], bool]] = {
+    "==": operator.eq,
+    "!=": operator.ne,
+    "<": operator.lt,
+    "<=": operator.le,
+    ">": operator.gt,
+    ">=": operator.ge,
+}
+
+
+class Condition(BaseModel):
+    """
+    A comparison of a context variable against a value, e.g. `hour >= 9`.
+    """
+
+    model_config = ConfigDict(frozen=True)
+
+    variable: str
+    operator: Literal["==", "!=", "<", "<=", ">", ">="]
+    value: bool | int | str
+
+    def holds(self, context: Mapping[str, Any]) -> bool:
+        if self.variable not in context:
+            return False
+
+        value = context[self.variable]
+
+        # `bool` is a subclass of `int`, so the types have to match exactly
+        if type(value) is not type(self.value):
+            return False
+
+        if self.operator not in ("==", "!=") and not isinstance(value, int):
+            return False
+
+        return _OPERATORS[self.operator](value, self.value)
+
+
 class Rule(RegistryModel):
     relationship: str
-    grants: tuple[Permission, ...]
+    grants: "tuple[Permission | Deny[Permission], ...]"
     attributes: tuple[Attribute, ...]
     rules: "tuple[Rule, ...]"
 
     include_fragments: tuple[str, ...] = pyd_field(default_factory=tuple)
 
+    # Universal rules confer the universal grants of the entity they reach
+    universal: bool = False
+
+    # The rule only applies when every condition holds in the context
+    conditions: tuple[Condition, ...] = pyd_field(default_factory=tuple)
+
     _src_entity: str = _OnRegister
     _relationship: str = _OnRegister
     _dst_entity: str = _OnRegister
@@ -68,14 +133,54 @@ class Rule(RegistryModel):
 
     @property
     def resolved_grants(self) -> set[Permission]:
-        grants = set(self.grants)
+        grants = {grant for grant in self.grants if not isinstance(grant, Deny)}
+
+        # A `*` rule reaches many entities, so it has no single destination
+        if self.universal and self._registry and isinstance(self._dst_entity, str):
+            grants.update(self._registry.universal_grants(self._dst_entity))
 
         for fragment in self.resolved_fragments:
             for grant in fragment.grants:
-                grants.add(grant)
+                if not isinstance(grant, Deny):
+                    grants.add(grant)
 
         return grants
 
+    @property
+    def resolved_denials(self) -> set[Permission]:
+        denials = {grant.deny for grant in self.grants if isinstance(grant, Deny)}
+
+        for fragment in self.resolved_fragments:
+            for grant in fragment.grants:
+                if isinstance(grant, Deny):
+                    denials.add(grant.deny)
+
+        return denials
+
+    def _confers(self, grants: set[Permission], permission: Permission) -> bool:
+        # Entities that don't declare how their grants imply each other
+        # cover every grant beneath one that is held
+        if not self._registry or not self._registry.declares_lattice(self._dst_entity):
+            return _covers(grants, permission)
+
+        return any(
+            permission in self._registry.implied_grants(self._dst_entity, grant)
+            for grant in grants
+        )
+
+    def applies(self, context: Mapping[str, Any] | None = None) -> bool:
+        return all(condition.holds(context or {}) for condition in self.conditions)
+
+    def allows(
+        self, permission: Permission, context: Mapping[str, Any] | None = None
+    ) -> bool:
+        if not self.applies(context):
+            return False
+
+        return self._confers(self.resolved_grants, permission) and not self._confers(
+            self.resolved_denials, permission
+        )
+
     @property
     def resolved_rules(self) -> "Generator[Rule, None, None]":
         yield from self.rules
@@ -83,15 +188,32 @@ class Rule(RegistryModel):
         for fragment in self.resolved_fragments:
             yield from fragment.rules
 
+    def applicable_rules(
+        self, context: Mapping[str, Any] | None = None
+    ) -> "Generator[Rule, None, None]":
+        """
+        The nested rules to follow in `context`. A rule whose conditions fail
+        applies to nothing beneath it either, so it has none.
+        """
+
+        if not self.applies(context):
+            return
+
+        yield from self.resolved_rules
+
 
 class Universal(Rule):
     relationship: Literal["*"]
 
 
 class Fragment(RegistryModel):
-    grants: tuple[Permission, ...]
+    grants: "tuple[Permission | Deny[Permission], ...]"
     rules: tuple[Rule, ...]
 
+    # Whether the fragment can include itself through its rules, so that its
+    # policy applies at any depth
+    recursive: bool = False
+
     _entity: str = _OnRegister
     _fragment: str = _OnRegister
 
@@ -131,6 +253,13 @@ class RegistryEntity:
     fragments: dict[str, RegistryFragment] = dc_field(default_factory=dict)
     rules: dict[str, RegistryRule] = dc_field(default_factory=dict)
 
+    # Every declared grant, mapped to the grants it confers (itself included),
+    # when the entity declares how its grants imply each other
+    grants: dict[Permission, tuple[Permission, ...]] = dc_field(default_factory=dict)
+
+    # The grants universal rules confer, when declared
+    universal: tuple[Permission, ...] | None = None
+
 
 _T = TypeVar("_T", bound=type[RegistryModel])
 _F = TypeVar("_F", bound=type[Fragment])
@@ -181,6 +310,41 @@ class Registry:
         reg_fragment = reg_entity.fragments[fragment]
         return reg_fragment.fragment_singleton
 
+    def register_grants(
+        self, entity: str, grants: dict[Permission, tuple[Permission, ...]]
+    ) -> None:
+        reg_entity = self.entities.get(entity, RegistryEntity())
+        self.entities[entity] = reg_entity
+
+        reg_entity.grants.update(grants)
+
+    def declares_lattice(self, entity: str) -> bool:
+        reg_entity = self.entities.get(entity)
+
+        return reg_entity is not None and bool(reg_entity.grants)
+
+    def implied_grants(self, entity: str, grant: Permission) -> tuple[Permission, ...]:
+        reg_entity = self.entities.get(entity)
+
+        if reg_entity is None or grant not in reg_entity.grants:
+            return (grant,)
+
+        return reg_entity.grants[grant]
+
+    def register_universal(self, entity: str, grants: tuple[Permission, ...]) -> None:
+        reg_entity = self.entities.get(entity, RegistryEntity())
+        self.entities[entity] = reg_entity
+
+        reg_entity.universal = (reg_entity.universal or ()) + grants
+
+    def universal_grants(self, entity: str) -> tuple[Permission, ...]:
+        reg_entity = self.entities.get(entity)
+
+        if reg_entity is None or reg_entity.universal is None:
+            return _DEFAULT_UNIVERSAL
+
+        return reg_entity.universal
+
     def register_relationship(
         self, src_entity: str, relationship: str, dst_entity: str
     ) -> Callable[[_R], _R]: